@@ -0,0 +1,793 @@
+-- pgdatatypes_plus 0.0.1 -> 0.0.2
+
+-- emailaddr now sorts domain-first (label by label from the TLD inward)
+-- instead of plain string order, and compares the domain case-insensitively.
+-- Every btree index built with the old ordering is out of order and has to be
+-- rebuilt. A unique index that holds the same mailbox with differently cased
+-- domains fails to rebuild; remove the duplicates and run the update again.
+DO $$
+DECLARE
+    idx regclass;
+BEGIN
+    FOR idx IN
+        SELECT DISTINCT i.indexrelid::regclass
+        FROM pg_index i
+        JOIN pg_opclass opc ON opc.oid = ANY (i.indclass::oid[])
+        JOIN pg_am am ON am.oid = opc.opcmethod
+        WHERE am.amname = 'btree'
+          AND opc.opcintype = 'emailaddr'::regtype
+    LOOP
+        RAISE NOTICE 'rebuilding index % for the new emailaddr ordering', idx;
+        EXECUTE format('REINDEX INDEX %s', idx);
+    END LOOP;
+END
+$$;
+
+-- Hash support, so emailaddr and twid can be used in hash joins, hash
+-- aggregates and USING hash indexes. emailaddr_hash folds the domain to
+-- lowercase, matching emailaddr equality. twid gets its hash support with
+-- the new twid type below.
+CREATE FUNCTION emailaddr_hash("value" emailaddr) RETURNS integer
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_hash_wrapper';
+
+CREATE OPERATOR FAMILY emailaddr_hash_ops USING hash;
+CREATE OPERATOR CLASS emailaddr_hash_ops DEFAULT FOR TYPE emailaddr USING hash FAMILY emailaddr_hash_ops AS
+    OPERATOR 1 = (emailaddr, emailaddr),
+    FUNCTION 1 emailaddr_hash(emailaddr);
+
+-- emailaddr switched from pgrx's serde/CBOR encoding to a native varlena
+-- layout: a format version byte, the offset of the '@' and the raw address.
+-- Values written by 0.0.1 carry no version byte and are still read
+-- transparently; they are rewritten in the new layout whenever they are
+-- updated, or all at once with
+--   ALTER TABLE t ALTER COLUMN c TYPE emailaddr USING c::text::emailaddr;
+-- The I/O functions keep their names and symbols but no longer accept NULL.
+ALTER FUNCTION emailaddr_in(cstring) STRICT;
+ALTER FUNCTION emailaddr_out(emailaddr) STRICT;
+
+-- twid is now a fixed-length, pass-by-value type: the ID packed into an
+-- int8 instead of a CBOR-encoded varlena. A type's storage can't be changed
+-- in place, so the 0.0.1 type is renamed to twid_0_0_1, the new twid is
+-- created next to it, every twid column is converted through a cast that
+-- reads the old values, and the old type is dropped.
+--
+-- Only table columns, and the indexes, constraints and defaults on them,
+-- can be converted. Refuse to upgrade while anything else uses twid:
+-- domains, composite types, ranges, views or functions, and defaults,
+-- constraints or index predicates holding a twid constant, which 0.0.2
+-- can't read.
+DO $$
+DECLARE
+    dependents text;
+BEGIN
+    WITH twid_objects AS (
+        SELECT 'pg_type'::regclass AS classid, t.oid AS objid
+        FROM pg_type t
+        WHERE t.oid IN ('twid'::regtype, 'twid[]'::regtype)
+      UNION
+        -- the operators, functions, casts and operator classes of twid
+        SELECT d.classid, d.objid
+        FROM pg_depend d
+        JOIN pg_depend e ON e.classid = d.classid AND e.objid = d.objid AND e.deptype = 'e'
+        WHERE d.refclassid = 'pg_type'::regclass
+          AND d.refobjid IN ('twid'::regtype, 'twid[]'::regtype)
+    )
+    SELECT string_agg(DISTINCT pg_describe_object(d.classid, d.objid, 0), ', ')
+    INTO dependents
+    FROM pg_depend d
+    JOIN twid_objects o ON o.classid = d.refclassid AND o.objid = d.refobjid
+    LEFT JOIN pg_class c ON d.classid = 'pg_class'::regclass AND c.oid = d.objid
+    WHERE d.deptype = 'n'
+      AND NOT EXISTS (
+          SELECT FROM pg_depend e
+          WHERE e.classid = d.classid AND e.objid = d.objid AND e.deptype = 'e'
+      )
+      AND NOT (d.objsubid > 0 AND c.relkind IN ('r', 'p', 'f'))
+      AND NOT (
+          (c.relkind IN ('i', 'I')
+           OR d.classid IN ('pg_constraint'::regclass, 'pg_attrdef'::regclass, 'pg_statistic_ext'::regclass))
+          AND o.classid <> 'pg_type'::regclass
+      );
+
+    IF dependents IS NOT NULL THEN
+        RAISE EXCEPTION 'cannot convert twid to its 0.0.2 storage format while other objects use it'
+            USING DETAIL = format('Used by: %s.', dependents),
+                  HINT = 'Drop these objects or change them to use text, run ALTER EXTENSION pgdatatypes_plus UPDATE, '
+                         'then recreate them.';
+    END IF;
+END
+$$;
+
+ALTER TYPE twid RENAME TO twid_0_0_1;
+ALTER FUNCTION twid_in(cstring) RENAME TO twid_0_0_1_in;
+ALTER FUNCTION cast_text_to_twid(text) RENAME TO cast_text_to_twid_0_0_1;
+ALTER FUNCTION twid(text) RENAME TO twid_0_0_1;
+ALTER OPERATOR CLASS twid_btree_ops USING btree RENAME TO twid_0_0_1_btree_ops;
+ALTER OPERATOR FAMILY twid_btree_ops USING btree RENAME TO twid_0_0_1_btree_ops;
+
+CREATE TYPE twid;
+
+CREATE FUNCTION twid_in(cstring) RETURNS twid
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'twid_in_wrapper';
+
+CREATE FUNCTION twid_out(twid) RETURNS cstring
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'twid_out_wrapper';
+
+CREATE TYPE twid (
+    INTERNALLENGTH = 8,
+    PASSEDBYVALUE,
+    ALIGNMENT = double,
+    INPUT = twid_in,
+    OUTPUT = twid_out,
+    STORAGE = plain
+);
+
+CREATE FUNCTION twid_eq("left" twid, "right" twid) RETURNS boolean
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'twid_eq_wrapper';
+
+CREATE FUNCTION twid_ne("left" twid, "right" twid) RETURNS boolean
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'twid_ne_wrapper';
+
+CREATE FUNCTION twid_lt("left" twid, "right" twid) RETURNS boolean
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'twid_lt_wrapper';
+
+CREATE FUNCTION twid_le("left" twid, "right" twid) RETURNS boolean
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'twid_le_wrapper';
+
+CREATE FUNCTION twid_gt("left" twid, "right" twid) RETURNS boolean
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'twid_gt_wrapper';
+
+CREATE FUNCTION twid_ge("left" twid, "right" twid) RETURNS boolean
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'twid_ge_wrapper';
+
+CREATE FUNCTION twid_cmp("left" twid, "right" twid) RETURNS integer
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'twid_cmp_wrapper';
+
+CREATE FUNCTION twid_hash("value" twid) RETURNS integer
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'twid_hash_wrapper';
+
+CREATE OPERATOR = (
+    LEFTARG = twid, RIGHTARG = twid, FUNCTION = twid_eq,
+    COMMUTATOR = =, NEGATOR = <>, RESTRICT = eqsel, JOIN = eqjoinsel, HASHES, MERGES
+);
+CREATE OPERATOR <> (
+    LEFTARG = twid, RIGHTARG = twid, FUNCTION = twid_ne,
+    COMMUTATOR = <>, NEGATOR = =, RESTRICT = neqsel, JOIN = neqjoinsel
+);
+CREATE OPERATOR < (
+    LEFTARG = twid, RIGHTARG = twid, FUNCTION = twid_lt,
+    COMMUTATOR = >, NEGATOR = >=, RESTRICT = scalarltsel, JOIN = scalarltjoinsel
+);
+CREATE OPERATOR <= (
+    LEFTARG = twid, RIGHTARG = twid, FUNCTION = twid_le,
+    COMMUTATOR = >=, NEGATOR = >, RESTRICT = scalarlesel, JOIN = scalarlejoinsel
+);
+CREATE OPERATOR > (
+    LEFTARG = twid, RIGHTARG = twid, FUNCTION = twid_gt,
+    COMMUTATOR = <, NEGATOR = <=, RESTRICT = scalargtsel, JOIN = scalargtjoinsel
+);
+CREATE OPERATOR >= (
+    LEFTARG = twid, RIGHTARG = twid, FUNCTION = twid_ge,
+    COMMUTATOR = <=, NEGATOR = <, RESTRICT = scalargesel, JOIN = scalargejoinsel
+);
+
+CREATE OPERATOR FAMILY twid_btree_ops USING btree;
+CREATE OPERATOR CLASS twid_btree_ops DEFAULT FOR TYPE twid USING btree FAMILY twid_btree_ops AS
+    OPERATOR 1 <,
+    OPERATOR 2 <=,
+    OPERATOR 3 =,
+    OPERATOR 4 >=,
+    OPERATOR 5 >,
+    FUNCTION 1 twid_cmp(twid, twid);
+
+CREATE OPERATOR FAMILY twid_hash_ops USING hash;
+CREATE OPERATOR CLASS twid_hash_ops DEFAULT FOR TYPE twid USING hash FAMILY twid_hash_ops AS
+    OPERATOR 1 = (twid, twid),
+    FUNCTION 1 twid_hash(twid);
+
+CREATE FUNCTION cast_twid_to_text("input" twid) RETURNS text
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'cast_twid_to_text_wrapper';
+CREATE CAST (twid AS text) WITH FUNCTION cast_twid_to_text(twid) AS ASSIGNMENT;
+
+CREATE FUNCTION cast_text_to_twid("input" text) RETURNS twid
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'cast_text_to_twid_wrapper';
+CREATE CAST (text AS twid) WITH FUNCTION cast_text_to_twid(text) AS ASSIGNMENT;
+
+CREATE FUNCTION twid("input" text) RETURNS twid
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'twid_wrapper';
+
+CREATE FUNCTION twid_gender("input" twid) RETURNS text
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'twid_gender_wrapper';
+
+CREATE FUNCTION twid_region("input" twid) RETURNS text
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'twid_region_wrapper';
+
+CREATE FUNCTION twid_from_legacy(twid_0_0_1) RETURNS twid
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'twid_from_legacy_wrapper';
+CREATE CAST (twid_0_0_1 AS twid) WITH FUNCTION twid_from_legacy(twid_0_0_1) AS ASSIGNMENT;
+
+DO $$
+DECLARE
+    col record;
+BEGIN
+    FOR col IN
+        SELECT a.attrelid::regclass AS rel, a.attname, a.atttypid = 'twid_0_0_1[]'::regtype AS is_array
+        FROM pg_attribute a
+        JOIN pg_class c ON c.oid = a.attrelid
+        WHERE a.atttypid IN ('twid_0_0_1'::regtype, 'twid_0_0_1[]'::regtype)
+          AND NOT a.attisdropped
+          AND a.attinhcount = 0
+          AND c.relkind IN ('r', 'p', 'f')
+    LOOP
+        RAISE NOTICE 'converting column % of % to the new twid storage format', col.attname, col.rel;
+        EXECUTE format('ALTER TABLE %s ALTER COLUMN %I TYPE %s', col.rel, col.attname,
+                       CASE WHEN col.is_array THEN 'twid[]' ELSE 'twid' END);
+    END LOOP;
+END
+$$;
+
+-- Only the 0.0.1 functions, operators, operator classes and casts are left
+DROP TYPE twid_0_0_1 CASCADE;
+
+-- Binary send/receive support, used by COPY ... (FORMAT binary) and by
+-- drivers requesting binary results.
+CREATE FUNCTION emailaddr_recv(internal) RETURNS emailaddr
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_recv_wrapper';
+
+CREATE FUNCTION emailaddr_send(emailaddr) RETURNS bytea
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_send_wrapper';
+
+ALTER TYPE emailaddr SET (RECEIVE = emailaddr_recv, SEND = emailaddr_send);
+
+CREATE FUNCTION twid_recv(internal) RETURNS twid
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'twid_recv_wrapper';
+
+CREATE FUNCTION twid_send(twid) RETURNS bytea
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'twid_send_wrapper';
+
+ALTER TYPE twid SET (RECEIVE = twid_recv, SEND = twid_send);
+
+-- Accessors for the parts of an address
+CREATE FUNCTION emailaddr_local("input" emailaddr) RETURNS text
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_local_wrapper';
+
+CREATE FUNCTION emailaddr_domain("input" emailaddr) RETURNS text
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_domain_wrapper';
+
+CREATE FUNCTION emailaddr_tld("input" emailaddr) RETURNS text
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_tld_wrapper';
+
+CREATE FUNCTION emailaddr_subaddress("input" emailaddr) RETURNS text
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_subaddress_wrapper';
+
+-- Public Suffix List lookups. STABLE, as the list in effect depends on
+-- pgdatatypes_plus.public_suffix_list.
+CREATE FUNCTION emailaddr_public_suffix("input" emailaddr) RETURNS text
+    STABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_public_suffix_wrapper';
+
+CREATE FUNCTION emailaddr_registrable_domain("input" emailaddr) RETURNS text
+    STABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_registrable_domain_wrapper';
+
+CREATE FUNCTION emailaddr_same_organization("a" emailaddr, "b" emailaddr) RETURNS boolean
+    STABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_same_organization_wrapper';
+
+CREATE FUNCTION public_suffix_list_size() RETURNS bigint
+    STABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'public_suffix_list_size_wrapper';
+
+-- Statement trigger on the config tables below, which makes every backend
+-- reload its cached copy of the table once the change commits
+CREATE FUNCTION pgdatatypes_plus_config_changed() RETURNS trigger
+    LANGUAGE c AS 'MODULE_PATHNAME', 'pgdatatypes_plus_config_changed_wrapper';
+
+-- Provider-aware canonical addresses and the ~= equivalence operator
+CREATE FUNCTION emailaddr_canonical("input" emailaddr) RETURNS emailaddr
+    STABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_canonical_wrapper';
+
+CREATE FUNCTION emailaddr_canonical_builtin("input" emailaddr) RETURNS emailaddr
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_canonical_builtin_wrapper';
+
+-- Canonicalisation rules per provider domain, overriding the built-in rules
+CREATE TABLE emailaddr_canonical_rules (
+    domain text PRIMARY KEY CHECK (domain = lower(domain)),
+    canonical_domain text CHECK (canonical_domain = lower(canonical_domain)),
+    strip_dots boolean NOT NULL DEFAULT false,
+    strip_subaddress boolean NOT NULL DEFAULT false,
+    subaddress_separator text NOT NULL DEFAULT '+' CHECK (length(subaddress_separator) = 1),
+    lowercase_local boolean NOT NULL DEFAULT false
+);
+SELECT pg_catalog.pg_extension_config_dump('emailaddr_canonical_rules', '');
+
+CREATE TRIGGER emailaddr_canonical_rules_changed
+    AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON emailaddr_canonical_rules
+    FOR EACH STATEMENT EXECUTE FUNCTION pgdatatypes_plus_config_changed();
+
+CREATE FUNCTION emailaddr_equivalent(emailaddr, emailaddr) RETURNS boolean
+    STABLE STRICT PARALLEL SAFE
+    LANGUAGE sql
+    AS 'SELECT @extschema@.emailaddr_canonical($1) OPERATOR(@extschema@.=) @extschema@.emailaddr_canonical($2)';
+
+CREATE OPERATOR ~= (
+    LEFTARG = emailaddr,
+    RIGHTARG = emailaddr,
+    FUNCTION = emailaddr_equivalent,
+    COMMUTATOR = ~=,
+    RESTRICT = eqsel,
+    JOIN = eqjoinsel
+);
+
+-- Conversion between the Unicode and ASCII (A-label) forms of
+-- internationalised domains
+CREATE FUNCTION emailaddr_to_ascii("input" emailaddr) RETURNS emailaddr
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_to_ascii_wrapper';
+
+CREATE FUNCTION emailaddr_to_unicode("input" emailaddr) RETURNS emailaddr
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_to_unicode_wrapper';
+
+-- What the text cast and emailaddr(text) accept now depends on the
+-- validation profile in pgdatatypes_plus.email_validation, so they are
+-- STABLE. Type input and receive accept an address valid under any profile
+-- and stay IMMUTABLE, so dumps and binary COPY load back.
+ALTER FUNCTION cast_text_to_emailaddr(text) STABLE;
+ALTER FUNCTION emailaddr(text) STABLE;
+
+-- Validation diagnostics
+CREATE TYPE emailaddr_validation_result AS (
+    valid boolean,
+    reason_code text,
+    message text,
+    "position" integer,
+    part text
+);
+
+CREATE FUNCTION emailaddr_validate("input" text) RETURNS emailaddr_validation_result
+    STABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_validate_wrapper';
+
+-- Classification as free provider, disposable address or role account
+CREATE TYPE emailaddr_classification AS (
+    free_provider boolean,
+    disposable boolean,
+    role_account boolean
+);
+
+-- Entries added to the built-in lists of emailaddr_classify(). value is a
+-- domain for free_provider and disposable, a local part for role_account.
+CREATE TABLE emailaddr_classification_entries (
+    kind text NOT NULL CHECK (kind IN ('free_provider', 'disposable', 'role_account')),
+    value text NOT NULL,
+    PRIMARY KEY (kind, value)
+);
+SELECT pg_catalog.pg_extension_config_dump('emailaddr_classification_entries', '');
+
+CREATE TRIGGER emailaddr_classification_entries_changed
+    AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON emailaddr_classification_entries
+    FOR EACH STATEMENT EXECUTE FUNCTION pgdatatypes_plus_config_changed();
+
+CREATE FUNCTION emailaddr_classify("input" emailaddr) RETURNS emailaddr_classification
+    STABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_classify_wrapper';
+
+-- Domain typo suggestions. Domains emailaddr_suggest() corrects typos
+-- towards, in addition to the built-in popular mail domains:
+CREATE TABLE emailaddr_known_domains (
+    domain text PRIMARY KEY CHECK (domain = lower(domain))
+);
+SELECT pg_catalog.pg_extension_config_dump('emailaddr_known_domains', '');
+
+CREATE TRIGGER emailaddr_known_domains_changed
+    AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON emailaddr_known_domains
+    FOR EACH STATEMENT EXECUTE FUNCTION pgdatatypes_plus_config_changed();
+
+CREATE FUNCTION emailaddr_suggest("input" emailaddr) RETURNS emailaddr
+    STABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_suggest_wrapper';
+
+-- Masked addresses for display
+CREATE FUNCTION emailaddr_mask("input" emailaddr, "style" text DEFAULT 'first_char') RETURNS text
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_mask_wrapper';
+
+-- Hashed identifiers for audience matching. STABLE, as the HMAC key comes
+-- from pgdatatypes_plus.hash_key.
+CREATE FUNCTION emailaddr_hashed_id(
+    "input" emailaddr,
+    "algorithm" text DEFAULT 'sha256',
+    "normalization" text DEFAULT 'standard'
+) RETURNS bytea
+    STABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_hashed_id_wrapper';
+
+CREATE FUNCTION emailaddr_hashed_id(
+    "input" text,
+    "algorithm" text DEFAULT 'sha256',
+    "normalization" text DEFAULT 'standard'
+) RETURNS bytea
+    STABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_hashed_id_text_wrapper';
+
+-- email <@ 'example.com': the address is at example.com or a subdomain of it.
+-- A SQL function so the planner inlines it into a range over the
+-- domain-first ordering, which a btree index on the column can serve.
+-- Not STRICT, which would keep it from being inlined.
+CREATE FUNCTION emailaddr_domain_range_start("domain" text) RETURNS emailaddr
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_domain_range_start_wrapper';
+
+CREATE FUNCTION emailaddr_domain_range_end("domain" text) RETURNS emailaddr
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_domain_range_end_wrapper';
+
+CREATE FUNCTION emailaddr_in_domain(emailaddr, text) RETURNS boolean
+    IMMUTABLE PARALLEL SAFE
+    LANGUAGE sql
+    AS 'SELECT $1 OPERATOR(@extschema@.>=) @extschema@.emailaddr_domain_range_start($2)
+           AND $1 OPERATOR(@extschema@.<) @extschema@.emailaddr_domain_range_end($2)';
+
+CREATE OPERATOR <@ (
+    LEFTARG = emailaddr,
+    RIGHTARG = text,
+    FUNCTION = emailaddr_in_domain
+);
+
+-- Pattern matching on emailaddr and the emailaddr_pattern_ops operator class
+CREATE FUNCTION emailaddr_pattern_lt("a" emailaddr, "b" emailaddr) RETURNS boolean
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_pattern_lt_wrapper';
+
+CREATE FUNCTION emailaddr_pattern_le("a" emailaddr, "b" emailaddr) RETURNS boolean
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_pattern_le_wrapper';
+
+CREATE FUNCTION emailaddr_pattern_ge("a" emailaddr, "b" emailaddr) RETURNS boolean
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_pattern_ge_wrapper';
+
+CREATE FUNCTION emailaddr_pattern_gt("a" emailaddr, "b" emailaddr) RETURNS boolean
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_pattern_gt_wrapper';
+
+CREATE FUNCTION emailaddr_pattern_cmp("a" emailaddr, "b" emailaddr) RETURNS integer
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_pattern_cmp_wrapper';
+
+CREATE FUNCTION emailaddr_text_pattern_lt("a" emailaddr, "b" text) RETURNS boolean
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_text_pattern_lt_wrapper';
+
+CREATE FUNCTION emailaddr_text_pattern_le("a" emailaddr, "b" text) RETURNS boolean
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_text_pattern_le_wrapper';
+
+CREATE FUNCTION emailaddr_text_pattern_ge("a" emailaddr, "b" text) RETURNS boolean
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_text_pattern_ge_wrapper';
+
+CREATE FUNCTION emailaddr_text_pattern_gt("a" emailaddr, "b" text) RETURNS boolean
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_text_pattern_gt_wrapper';
+
+CREATE FUNCTION emailaddr_text_pattern_cmp("a" emailaddr, "b" text) RETURNS integer
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_text_pattern_cmp_wrapper';
+
+CREATE FUNCTION emailaddr_like_prefix("pattern" text) RETURNS text
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_like_prefix_wrapper';
+
+CREATE FUNCTION emailaddr_regex_prefix("pattern" text) RETURNS text
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_regex_prefix_wrapper';
+
+CREATE FUNCTION emailaddr_prefix_end("prefix" text) RETURNS text
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_prefix_end_wrapper';
+
+-- Byte-wise ordering for pattern matching, like text_pattern_ops. The
+-- (emailaddr, text) operators compare with the range bounds LIKE and ~
+-- derive from their pattern.
+CREATE OPERATOR ~<~ (
+    LEFTARG = emailaddr, RIGHTARG = emailaddr, FUNCTION = emailaddr_pattern_lt,
+    COMMUTATOR = ~>~, NEGATOR = ~>=~, RESTRICT = scalarltsel, JOIN = scalarltjoinsel
+);
+CREATE OPERATOR ~<=~ (
+    LEFTARG = emailaddr, RIGHTARG = emailaddr, FUNCTION = emailaddr_pattern_le,
+    COMMUTATOR = ~>=~, NEGATOR = ~>~, RESTRICT = scalarlesel, JOIN = scalarlejoinsel
+);
+CREATE OPERATOR ~>=~ (
+    LEFTARG = emailaddr, RIGHTARG = emailaddr, FUNCTION = emailaddr_pattern_ge,
+    COMMUTATOR = ~<=~, NEGATOR = ~<~, RESTRICT = scalargesel, JOIN = scalargejoinsel
+);
+CREATE OPERATOR ~>~ (
+    LEFTARG = emailaddr, RIGHTARG = emailaddr, FUNCTION = emailaddr_pattern_gt,
+    COMMUTATOR = ~<~, NEGATOR = ~<=~, RESTRICT = scalargtsel, JOIN = scalargtjoinsel
+);
+
+CREATE OPERATOR ~<~ (
+    LEFTARG = emailaddr, RIGHTARG = text, FUNCTION = emailaddr_text_pattern_lt,
+    NEGATOR = ~>=~, RESTRICT = scalarltsel, JOIN = scalarltjoinsel
+);
+CREATE OPERATOR ~<=~ (
+    LEFTARG = emailaddr, RIGHTARG = text, FUNCTION = emailaddr_text_pattern_le,
+    NEGATOR = ~>~, RESTRICT = scalarlesel, JOIN = scalarlejoinsel
+);
+CREATE OPERATOR ~>=~ (
+    LEFTARG = emailaddr, RIGHTARG = text, FUNCTION = emailaddr_text_pattern_ge,
+    NEGATOR = ~<~, RESTRICT = scalargesel, JOIN = scalargejoinsel
+);
+CREATE OPERATOR ~>~ (
+    LEFTARG = emailaddr, RIGHTARG = text, FUNCTION = emailaddr_text_pattern_gt,
+    NEGATOR = ~<=~, RESTRICT = scalargtsel, JOIN = scalargtjoinsel
+);
+
+CREATE OPERATOR FAMILY emailaddr_pattern_ops USING btree;
+CREATE OPERATOR CLASS emailaddr_pattern_ops FOR TYPE emailaddr USING btree FAMILY emailaddr_pattern_ops AS
+    OPERATOR 1 ~<~ (emailaddr, emailaddr),
+    OPERATOR 2 ~<=~ (emailaddr, emailaddr),
+    OPERATOR 3 = (emailaddr, emailaddr),
+    OPERATOR 4 ~>=~ (emailaddr, emailaddr),
+    OPERATOR 5 ~>~ (emailaddr, emailaddr),
+    FUNCTION 1 emailaddr_pattern_cmp(emailaddr, emailaddr);
+ALTER OPERATOR FAMILY emailaddr_pattern_ops USING btree ADD
+    OPERATOR 1 ~<~ (emailaddr, text),
+    OPERATOR 2 ~<=~ (emailaddr, text),
+    OPERATOR 4 ~>=~ (emailaddr, text),
+    OPERATOR 5 ~>~ (emailaddr, text),
+    FUNCTION 1 emailaddr_text_pattern_cmp(emailaddr, text);
+
+-- LIKE, ILIKE and regular expressions on the address as text. LIKE and ~
+-- are SQL functions the planner inlines, so the range of their literal
+-- prefix can be looked up in an emailaddr_pattern_ops index. Not STRICT,
+-- which would keep them from being inlined.
+CREATE FUNCTION emailaddr_like(emailaddr, text) RETURNS boolean
+    IMMUTABLE PARALLEL SAFE
+    LANGUAGE sql
+    AS 'SELECT $1 OPERATOR(@extschema@.~>=~) @extschema@.emailaddr_like_prefix($2)
+           AND $1 OPERATOR(@extschema@.~<~) @extschema@.emailaddr_prefix_end(@extschema@.emailaddr_like_prefix($2))
+           AND $1::text OPERATOR(pg_catalog.~~) $2';
+
+CREATE FUNCTION emailaddr_not_like(emailaddr, text) RETURNS boolean
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE sql
+    AS 'SELECT $1::text OPERATOR(pg_catalog.!~~) $2';
+
+CREATE FUNCTION emailaddr_ilike(emailaddr, text) RETURNS boolean
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE sql
+    AS 'SELECT $1::text OPERATOR(pg_catalog.~~*) $2';
+
+CREATE FUNCTION emailaddr_not_ilike(emailaddr, text) RETURNS boolean
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE sql
+    AS 'SELECT $1::text OPERATOR(pg_catalog.!~~*) $2';
+
+CREATE FUNCTION emailaddr_regex_match(emailaddr, text) RETURNS boolean
+    IMMUTABLE PARALLEL SAFE
+    LANGUAGE sql
+    AS 'SELECT $1 OPERATOR(@extschema@.~>=~) @extschema@.emailaddr_regex_prefix($2)
+           AND $1 OPERATOR(@extschema@.~<~) @extschema@.emailaddr_prefix_end(@extschema@.emailaddr_regex_prefix($2))
+           AND $1::text OPERATOR(pg_catalog.~) $2';
+
+CREATE FUNCTION emailaddr_not_regex_match(emailaddr, text) RETURNS boolean
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE sql
+    AS 'SELECT $1::text OPERATOR(pg_catalog.!~) $2';
+
+CREATE FUNCTION emailaddr_iregex_match(emailaddr, text) RETURNS boolean
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE sql
+    AS 'SELECT $1::text OPERATOR(pg_catalog.~*) $2';
+
+CREATE FUNCTION emailaddr_not_iregex_match(emailaddr, text) RETURNS boolean
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE sql
+    AS 'SELECT $1::text OPERATOR(pg_catalog.!~*) $2';
+
+CREATE OPERATOR ~~ (LEFTARG = emailaddr, RIGHTARG = text, FUNCTION = emailaddr_like, NEGATOR = !~~);
+CREATE OPERATOR !~~ (LEFTARG = emailaddr, RIGHTARG = text, FUNCTION = emailaddr_not_like, NEGATOR = ~~);
+CREATE OPERATOR ~~* (LEFTARG = emailaddr, RIGHTARG = text, FUNCTION = emailaddr_ilike, NEGATOR = !~~*);
+CREATE OPERATOR !~~* (LEFTARG = emailaddr, RIGHTARG = text, FUNCTION = emailaddr_not_ilike, NEGATOR = ~~*);
+CREATE OPERATOR ~ (LEFTARG = emailaddr, RIGHTARG = text, FUNCTION = emailaddr_regex_match, NEGATOR = !~);
+CREATE OPERATOR !~ (LEFTARG = emailaddr, RIGHTARG = text, FUNCTION = emailaddr_not_regex_match, NEGATOR = ~);
+CREATE OPERATOR ~* (LEFTARG = emailaddr, RIGHTARG = text, FUNCTION = emailaddr_iregex_match, NEGATOR = !~*);
+CREATE OPERATOR !~* (LEFTARG = emailaddr, RIGHTARG = text, FUNCTION = emailaddr_not_iregex_match, NEGATOR = ~*);
+
+-- The mailbox type: an address with its display name
+CREATE TYPE mailbox;
+
+CREATE FUNCTION mailbox_in(cstring) RETURNS mailbox
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'mailbox_in_wrapper';
+
+CREATE FUNCTION mailbox_out(mailbox) RETURNS cstring
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'mailbox_out_wrapper';
+
+CREATE FUNCTION mailbox_recv(internal) RETURNS mailbox
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'mailbox_recv_wrapper';
+
+CREATE FUNCTION mailbox_send(mailbox) RETURNS bytea
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'mailbox_send_wrapper';
+
+CREATE TYPE mailbox (
+    INTERNALLENGTH = variable,
+    INPUT = mailbox_in,
+    OUTPUT = mailbox_out,
+    RECEIVE = mailbox_recv,
+    SEND = mailbox_send,
+    STORAGE = extended
+);
+
+CREATE FUNCTION mailbox("address" emailaddr, "display_name" text DEFAULT NULL) RETURNS mailbox
+    IMMUTABLE PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'mailbox_wrapper';
+
+CREATE FUNCTION mailbox_display_name("input" mailbox) RETURNS text
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'mailbox_display_name_wrapper';
+
+CREATE FUNCTION mailbox_address("input" mailbox) RETURNS emailaddr
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'mailbox_address_wrapper';
+
+CREATE FUNCTION cast_mailbox_to_emailaddr("input" mailbox) RETURNS emailaddr
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'cast_mailbox_to_emailaddr_wrapper';
+
+CREATE CAST (mailbox AS emailaddr)
+    WITH FUNCTION cast_mailbox_to_emailaddr(mailbox) AS ASSIGNMENT;
+
+-- Parsing RFC 5322 address lists, as in To: and Cc: headers
+CREATE FUNCTION parse_address_list("input" text) RETURNS TABLE (
+    "position" integer,
+    "display_name" text,
+    "address" emailaddr,
+    "group_name" text,
+    "fragment" text,
+    "error" text
+)
+    STABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'parse_address_list_wrapper';
+
+-- Finding addresses and IDs in free text
+CREATE FUNCTION extract_emailaddrs("text" text) RETURNS TABLE (
+    "address" emailaddr,
+    "position" integer,
+    "length" integer
+)
+    STABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'extract_emailaddrs_wrapper';
+
+CREATE FUNCTION extract_twids("text" text) RETURNS TABLE (
+    "twid" twid,
+    "position" integer,
+    "length" integer
+)
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'extract_twids_wrapper';
+
+-- Parsing and building mailto: URIs
+CREATE TYPE mailto_parts AS (
+    addresses emailaddr[],
+    headers jsonb
+);
+
+CREATE FUNCTION mailto_parse("uri" text) RETURNS mailto_parts
+    STABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'mailto_parse_wrapper';
+
+CREATE FUNCTION mailto_build("addresses" emailaddr[], "headers" jsonb DEFAULT NULL) RETURNS text
+    IMMUTABLE PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'mailto_build_wrapper';
+
+-- Type modifiers: emailaddr(n) holds addresses of at most n bytes, with the
+-- domain in A-label form, and emailaddr('domain') addresses at the domain or
+-- its subdomains. The input and receive functions keep their one-argument
+-- signatures and read the type modifier PostgreSQL passes them as the third
+-- argument.
+CREATE TABLE emailaddr_typmod_domains (
+    id integer GENERATED ALWAYS AS IDENTITY (MAXVALUE 32767) PRIMARY KEY,
+    domain text NOT NULL UNIQUE
+);
+GRANT SELECT ON emailaddr_typmod_domains TO PUBLIC;
+
+CREATE TRIGGER emailaddr_typmod_domains_changed
+    AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON emailaddr_typmod_domains
+    FOR EACH STATEMENT EXECUTE FUNCTION pgdatatypes_plus_config_changed();
+
+CREATE FUNCTION emailaddr_typmod_domains_in_use() RETURNS trigger
+    SET search_path = pg_catalog, pg_temp
+    LANGUAGE plpgsql
+    AS $$
+    DECLARE
+        -- NULL checks every domain
+        domain_id integer;
+        used_by text;
+    BEGIN
+        IF TG_LEVEL = 'ROW' THEN
+            domain_id := OLD.id;
+        END IF;
+
+        SELECT string_agg(used.object, ', ' ORDER BY used.object) INTO used_by
+        FROM (
+            SELECT format('column %I of %s', attname, attrelid::regclass) AS object, atttypmod AS typmod
+            FROM pg_attribute
+            WHERE atttypid IN ('@extschema@.emailaddr'::regtype, '@extschema@.emailaddr[]'::regtype)
+                AND NOT attisdropped
+            UNION ALL
+            SELECT format('type %s', oid::regtype), typtypmod
+            FROM pg_type
+            WHERE typbasetype IN ('@extschema@.emailaddr'::regtype, '@extschema@.emailaddr[]'::regtype)
+        ) AS used
+        WHERE used.typmod >> 16 > 0
+            AND (domain_id IS NULL OR used.typmod >> 16 = domain_id);
+
+        IF used_by IS NOT NULL THEN
+            RAISE EXCEPTION 'emailaddr type modifier domain is in use'
+                USING ERRCODE = 'dependent_objects_still_exist',
+                      DETAIL = format('Used by %s.', used_by);
+        END IF;
+        RETURN OLD;
+    END
+    $$;
+
+CREATE TRIGGER emailaddr_typmod_domains_in_use
+    BEFORE UPDATE OR DELETE ON emailaddr_typmod_domains
+    FOR EACH ROW EXECUTE FUNCTION emailaddr_typmod_domains_in_use();
+
+CREATE TRIGGER emailaddr_typmod_domains_truncate
+    BEFORE TRUNCATE ON emailaddr_typmod_domains
+    FOR EACH STATEMENT EXECUTE FUNCTION emailaddr_typmod_domains_in_use();
+
+CREATE FUNCTION emailaddr_typmod_in(cstring[]) RETURNS integer
+    IMMUTABLE STRICT
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_typmod_in_wrapper';
+
+CREATE FUNCTION emailaddr_typmod_out(integer) RETURNS cstring
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_typmod_out_wrapper';
+
+ALTER TYPE emailaddr SET (TYPMOD_IN = emailaddr_typmod_in, TYPMOD_OUT = emailaddr_typmod_out);
+
+-- The length coercion cast PostgreSQL applies when storing into an
+-- emailaddr(...) column
+CREATE FUNCTION emailaddr("input" emailaddr, "typmod" integer, "_explicit" boolean) RETURNS emailaddr
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_typmod_coerce_wrapper';
+
+CREATE CAST (emailaddr AS emailaddr)
+    WITH FUNCTION emailaddr(emailaddr, integer, boolean) AS IMPLICIT;
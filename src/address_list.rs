@@ -0,0 +1,224 @@
+use pgrx::prelude::*;
+use crate::email_addr::EmailAddr;
+use crate::error::InvalidValue;
+use crate::mailbox::{display_name, Mailbox, Scanner};
+
+/// One mailbox of an address list, or the text that could not be parsed
+/// as one.
+#[derive(Debug)]
+struct Entry {
+    /// The group the mailbox is listed in, as in `Team: a@x, b@x;`
+    group: Option<String>,
+    parsed: Result<Mailbox, Unparsed>,
+}
+
+#[derive(Debug)]
+struct Unparsed {
+    fragment: String,
+    error: InvalidValue,
+}
+
+/// Skips to the next of `delimiters` outside quoted strings, comments and
+/// angle brackets, to carry on after an entry that could not be parsed.
+fn skip_entry(scanner: &mut Scanner, delimiters: &[char]) {
+    let (mut quoted, mut comment, mut angle) = (false, 0, false);
+    while let Some(c) = scanner.peek() {
+        if !quoted && comment == 0 && !angle && delimiters.contains(&c) {
+            return;
+        }
+        scanner.bump();
+        match c {
+            '\\' if quoted || comment > 0 => {
+                scanner.bump();
+            }
+            '"' if comment == 0 => quoted = !quoted,
+            '(' if !quoted => comment += 1,
+            ')' if !quoted && comment > 0 => comment -= 1,
+            '<' if !quoted && comment == 0 => angle = true,
+            '>' if !quoted && comment == 0 => angle = false,
+            _ => {}
+        }
+    }
+}
+
+/// Reads the mailbox at the scanner, which must be followed by one of
+/// `delimiters` or the end of the input. On error the scanner is left at
+/// the next delimiter.
+fn list_entry(scanner: &mut Scanner, input: &str, delimiters: &[char]) -> Result<Mailbox, Unparsed> {
+    let start = scanner.pos();
+    let parsed = scanner.mailbox(delimiters).and_then(|mailbox| {
+        match scanner.peek() {
+            Some(c) if !delimiters.contains(&c) => {
+                Err(InvalidValue::new("invalid mailbox format").with_detail("Unexpected text after the address."))
+            }
+            _ => Ok(mailbox),
+        }
+    });
+
+    parsed.map_err(|error| {
+        scanner.set_pos(start);
+        skip_entry(scanner, delimiters);
+        Unparsed {
+            fragment: input[start..scanner.pos()].trim().to_string(),
+            error,
+        }
+    })
+}
+
+/// Parses an RFC 5322 address list, the value of a `To:` or `Cc:` header:
+/// mailboxes and groups separated by commas. An entry that can't be parsed
+/// doesn't stop the rest from being read. Empty entries are skipped, as
+/// RFC 5322 allows in obsolete syntax.
+fn parse_entries(input: &str) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut scanner = Scanner::new(input);
+
+    loop {
+        // An unterminated comment runs to the end, where this stops
+        let _ = scanner.skip_cfws();
+        match scanner.peek() {
+            None => return entries,
+            Some(',') => {
+                scanner.bump();
+                continue;
+            }
+            _ => {}
+        }
+
+        let start = scanner.pos();
+        let group = match scanner.phrase() {
+            Ok(words) if scanner.eat(':') => display_name(&words),
+            _ => {
+                scanner.set_pos(start);
+                let parsed = list_entry(&mut scanner, input, &[',']);
+                entries.push(Entry { group: None, parsed });
+                continue;
+            }
+        };
+
+        // The members of a group end at `;`, or leniently at the end of input
+        loop {
+            let _ = scanner.skip_cfws();
+            match scanner.peek() {
+                None => return entries,
+                Some(';') => {
+                    scanner.bump();
+                    break;
+                }
+                Some(',') => {
+                    scanner.bump();
+                }
+                _ => {
+                    let parsed = list_entry(&mut scanner, input, &[',', ';']);
+                    entries.push(Entry { group: group.clone(), parsed });
+                }
+            }
+        }
+    }
+}
+
+/// The mailboxes of an RFC 5322 address list such as a `To:` or `Cc:`
+/// header value, one row each, with their 1-based position in the list and
+/// the group they are listed in. Addresses are validated like `emailaddr`
+/// input. Text that can't be parsed as a mailbox gives a row with a NULL
+/// address, the `fragment` and the `error`, and the rest of the list is
+/// still read.
+#[pg_extern(stable, parallel_safe)]
+fn parse_address_list(
+    input: &str,
+) -> TableIterator<
+    'static,
+    (
+        name!(position, i32),
+        name!(display_name, Option<String>),
+        name!(address, Option<EmailAddr>),
+        name!(group_name, Option<String>),
+        name!(fragment, Option<String>),
+        name!(error, Option<String>),
+    ),
+> {
+    let rows = parse_entries(input).into_iter().enumerate().map(|(i, entry)| {
+        let position = i as i32 + 1;
+        match entry.parsed {
+            Ok(mailbox) => {
+                let name = mailbox.display_name().map(str::to_string);
+                (position, name, Some(mailbox.into_address()), entry.group, None, None)
+            }
+            Err(unparsed) => {
+                let error = match unparsed.error.detail {
+                    Some(detail) => format!("{}: {}", unparsed.error.reason, detail),
+                    None => unparsed.error.reason.to_string(),
+                };
+                (position, None, None, entry.group, Some(unparsed.fragment), Some(error))
+            }
+        }
+    });
+    TableIterator::new(rows.collect::<Vec<_>>())
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+
+    /// The entries as `group|mailbox`, or `group|!fragment` for errors
+    fn entries(input: &str) -> Vec<String> {
+        parse_entries(input)
+            .into_iter()
+            .map(|entry| {
+                let parsed = match entry.parsed {
+                    Ok(mailbox) => mailbox.to_string(),
+                    Err(unparsed) => format!("!{}", unparsed.fragment),
+                };
+                format!("{}|{}", entry.group.unwrap_or_default(), parsed)
+            })
+            .collect()
+    }
+
+    #[pg_test]
+    fn test_parse_entries() {
+        assert_eq!(
+            entries(r#""Chen, Mei" <mei@example.tw>, bob@example.com (Bob), Ann <ann@example.org>"#),
+            ["|\"Chen, Mei\" <mei@example.tw>", "|bob@example.com", "|Ann <ann@example.org>"]
+        );
+        assert_eq!(
+            entries("Team: a@example.com, B <b@example.com>;, c@example.com"),
+            ["Team|a@example.com", "Team|B <b@example.com>", "|c@example.com"]
+        );
+        assert_eq!(entries("undisclosed-recipients:;"), Vec::<String>::new());
+        assert_eq!(
+            entries("Mei\r\n Chen\r\n <mei@example.tw>,\r\n\t=?UTF-8?B?6Zmz576O?= <chen@example.tw>"),
+            ["|Mei Chen <mei@example.tw>", "|陳美 <chen@example.tw>"]
+        );
+        assert_eq!(entries(" , a@example.com,, "), ["|a@example.com"]);
+        assert_eq!(entries(""), Vec::<String>::new());
+    }
+
+    #[pg_test]
+    fn test_parse_entries_recovers() {
+        assert_eq!(
+            entries(r#"a@example.com, not an address, "Doe, J" <broken, b@example.com"#),
+            ["|a@example.com", "|!not an address", "|!\"Doe, J\" <broken, b@example.com"]
+        );
+        assert_eq!(
+            entries("Team: a@example.com, nope; b@example.com"),
+            ["Team|a@example.com", "Team|!nope", "|b@example.com"]
+        );
+        assert_eq!(entries("Mei <mei@example.tw> junk, b@example.com"), ["|!Mei <mei@example.tw> junk", "|b@example.com"]);
+    }
+
+    #[pg_test]
+    fn test_parse_address_list() {
+        let rows = Spi::get_one::<String>(
+            r#"SELECT string_agg(concat_ws('|', position, display_name, address, group_name, fragment), ';' ORDER BY position)
+               FROM parse_address_list('"Chen, Mei" <mei@example.tw>, Team: b@example.com;, oops')"#
+        ).unwrap();
+        assert_eq!(
+            rows.as_deref(),
+            Some("1|Chen, Mei|mei@example.tw;2|b@example.com|Team;3|oops")
+        );
+
+        let error = Spi::get_one::<String>("SELECT error FROM parse_address_list('oops')").unwrap();
+        assert!(error.is_some_and(|e| !e.is_empty()));
+    }
+}
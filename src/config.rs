@@ -0,0 +1,90 @@
+use pgrx::prelude::*;
+use pgrx::{AllocatedByPostgres, PgTrigger, PgTriggerError};
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::thread::LocalKey;
+
+/// The schema-qualified, quoted name of one of the extension's tables, so
+/// lookups don't depend on `search_path`.
+pub(crate) fn extension_table(name: &str) -> String {
+    let schema = Spi::get_one::<String>(
+        "SELECT extnamespace::regnamespace::text FROM pg_catalog.pg_extension \
+         WHERE extname = 'pgdatatypes_plus'",
+    )
+    .ok()
+    .flatten()
+    .unwrap_or_else(|| error!("extension \"pgdatatypes_plus\" is not installed"));
+
+    format!("{}.{}", schema, name)
+}
+
+thread_local! {
+    /// Config tables this backend keeps a cached copy of
+    static WATCHED: RefCell<HashSet<pg_sys::Oid>> = RefCell::new(HashSet::new());
+    /// Bumped whenever a watched table may have changed
+    static GENERATION: Cell<u64> = const { Cell::new(0) };
+}
+
+/// A per-backend copy of data loaded from a config table, with the
+/// generation it was loaded in.
+pub(crate) type TableCache<T> = RefCell<Option<(u64, Rc<T>)>>;
+
+pub(crate) fn init() {
+    unsafe {
+        pg_sys::CacheRegisterRelcacheCallback(Some(relcache_callback), pg_sys::Datum::from(0));
+    }
+}
+
+#[pg_guard]
+unsafe extern "C-unwind" fn relcache_callback(_arg: pg_sys::Datum, relid: pg_sys::Oid) {
+    // InvalidOid means the whole relcache was reset
+    let watched = relid == pg_sys::InvalidOid || WATCHED.with(|watched| watched.borrow().contains(&relid));
+    if watched {
+        GENERATION.with(|generation| generation.set(generation.get() + 1));
+    }
+}
+
+/// Returns the cached copy of the extension's config table `table`,
+/// loading it with `load` (given the qualified table name) the first time
+/// and again after the table has changed.
+pub(crate) fn cached_table<T>(
+    cache: &'static LocalKey<TableCache<T>>,
+    table: &str,
+    load: impl FnOnce(&str) -> T,
+) -> Rc<T> {
+    // Read before loading, so a change made while loading forces a reload
+    let generation = GENERATION.with(Cell::get);
+    let cached = cache.with(|cache| {
+        cache
+            .borrow()
+            .as_ref()
+            .filter(|(loaded, _)| *loaded == generation)
+            .map(|(_, value)| value.clone())
+    });
+    if let Some(value) = cached {
+        return value;
+    }
+
+    let table = extension_table(table);
+    let oid = Spi::get_one_with_args::<pg_sys::Oid>("SELECT $1::regclass::oid", &[table.as_str().into()])
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| error!("relation {} does not exist", table));
+    WATCHED.with(|watched| watched.borrow_mut().insert(oid));
+
+    let value = Rc::new(load(&table));
+    cache.with(|cache| *cache.borrow_mut() = Some((generation, value.clone())));
+    value
+}
+
+/// Statement trigger on the config tables: makes every backend reload its
+/// cached copy once the change commits.
+#[pg_trigger]
+fn pgdatatypes_plus_config_changed<'a>(
+    trigger: &'a PgTrigger<'a>,
+) -> Result<Option<PgHeapTuple<'a, AllocatedByPostgres>>, PgTriggerError> {
+    let relid = trigger.relation()?.oid();
+    unsafe { pg_sys::CacheInvalidateRelcacheByRelid(relid) };
+    Ok(None)
+}
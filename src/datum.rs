@@ -0,0 +1,163 @@
+use pgrx::prelude::*;
+use crate::error::{raise, InvalidValue};
+
+/// Implements the SQL mapping and calling-convention traits for a type that
+/// manages its own on-disk representation instead of deriving `PostgresType`.
+///
+/// The type must implement `FromDatum` and `IntoDatum`, and its `CREATE TYPE`
+/// must be declared in an `extension_sql!` block that `creates` it.
+macro_rules! impl_sql_type {
+    ($ty:ty, $sql_name:literal) => {
+        unsafe impl ::pgrx::pgrx_sql_entity_graph::metadata::SqlTranslatable for $ty {
+            fn argument_sql() -> Result<
+                ::pgrx::pgrx_sql_entity_graph::metadata::SqlMapping,
+                ::pgrx::pgrx_sql_entity_graph::metadata::ArgumentError,
+            > {
+                Ok(::pgrx::pgrx_sql_entity_graph::metadata::SqlMapping::As(String::from($sql_name)))
+            }
+
+            fn return_sql() -> Result<
+                ::pgrx::pgrx_sql_entity_graph::metadata::Returns,
+                ::pgrx::pgrx_sql_entity_graph::metadata::ReturnsError,
+            > {
+                Ok(::pgrx::pgrx_sql_entity_graph::metadata::Returns::One(
+                    ::pgrx::pgrx_sql_entity_graph::metadata::SqlMapping::As(String::from($sql_name)),
+                ))
+            }
+        }
+
+        unsafe impl ::pgrx::datum::UnboxDatum for $ty {
+            type As<'src> = Self where Self: 'src;
+
+            unsafe fn unbox<'src>(datum: ::pgrx::datum::Datum<'src>) -> Self::As<'src>
+            where
+                Self: 'src,
+            {
+                <Self as ::pgrx::datum::FromDatum>::from_datum(datum.sans_lifetime(), false).unwrap()
+            }
+        }
+
+        unsafe impl<'fcx> ::pgrx::callconv::ArgAbi<'fcx> for $ty {
+            unsafe fn unbox_arg_unchecked(arg: ::pgrx::callconv::Arg<'_, 'fcx>) -> Self {
+                let index = arg.index();
+                arg.unbox_arg_using_from_datum()
+                    .unwrap_or_else(|| panic!("argument {index} must not be null"))
+            }
+
+            unsafe fn unbox_nullable_arg(
+                arg: ::pgrx::callconv::Arg<'_, 'fcx>,
+            ) -> ::pgrx::nullable::Nullable<Self> {
+                arg.unbox_arg_using_from_datum().into()
+            }
+        }
+
+        unsafe impl ::pgrx::callconv::BoxRet for $ty {
+            unsafe fn box_into<'fcx>(
+                self,
+                fcinfo: &mut ::pgrx::callconv::FcInfo<'fcx>,
+            ) -> ::pgrx::datum::Datum<'fcx> {
+                match ::pgrx::datum::IntoDatum::into_datum(self) {
+                    None => fcinfo.return_null(),
+                    Some(datum) => fcinfo.return_raw_datum(datum),
+                }
+            }
+        }
+    };
+}
+
+pub(crate) use impl_sql_type;
+
+/// Consumes the unread rest of the message buffer handed to a type's
+/// `RECEIVE` function.
+///
+/// # Safety
+///
+/// `buf.data` must point to at least `buf.len` valid bytes.
+pub(crate) unsafe fn take_message_bytes(buf: &mut pg_sys::StringInfoData) -> &[u8] {
+    let start = buf.cursor as usize;
+    let len = (buf.len - buf.cursor) as usize;
+    buf.cursor = buf.len;
+
+    std::slice::from_raw_parts(buf.data.add(start) as *const u8, len)
+}
+
+/// Extracts the text of a 0.0.1 value: a CBOR map with the single text
+/// entry `"data"`, as written by pgrx's serde-based `PostgresType`.
+pub(crate) fn decode_legacy_cbor(bytes: &[u8]) -> Option<&str> {
+    let rest = bytes.strip_prefix(b"\xa1\x64data")?;
+    let (len, rest) = match *rest.first()? {
+        n @ 0x60..=0x77 => ((n - 0x60) as usize, &rest[1..]),
+        0x78 => (*rest.get(1)? as usize, &rest[2..]),
+        0x79 => (u16::from_be_bytes([*rest.get(1)?, *rest.get(2)?]) as usize, &rest[3..]),
+        _ => return None,
+    };
+
+    std::str::from_utf8(rest.get(..len)?).ok()
+}
+
+/// Reports invalid text input to the input function of `type_name`, as
+/// `22P02 invalid_text_representation`.
+///
+/// When the caller passed an error-save context (`pg_input_is_valid()`,
+/// `COPY ... ON_ERROR`, PG16 and later) the error is recorded there and
+/// this function returns; the input function must then return NULL.
+/// Otherwise the error is raised as usual.
+pub(crate) fn report_input_error(fcinfo: pg_sys::FunctionCallInfo, type_name: &str, err: InvalidValue) {
+    let message = format!("invalid input syntax for type {}: {}", type_name, err.reason);
+    report_soft_error(fcinfo, PgSqlErrorCode::ERRCODE_INVALID_TEXT_REPRESENTATION, message, err);
+}
+
+/// Reports input that doesn't fit the type modifier given to an input
+/// function, as `code`, the same way as [`report_input_error`].
+pub(crate) fn report_modifier_error(fcinfo: pg_sys::FunctionCallInfo, code: PgSqlErrorCode, err: InvalidValue) {
+    let message = err.reason.to_string();
+    report_soft_error(fcinfo, code, message, err);
+}
+
+/// The type modifier PostgreSQL passes input and receive functions as their
+/// third argument, -1 for none. The functions may be declared with just the
+/// first argument, so it is read from `fcinfo` when present.
+pub(crate) fn input_typmod(fcinfo: pg_sys::FunctionCallInfo) -> i32 {
+    unsafe {
+        if (*fcinfo).nargs < 3 {
+            return -1;
+        }
+        pgrx::fcinfo::pg_getarg::<i32>(fcinfo, 2).unwrap_or(-1)
+    }
+}
+
+fn report_soft_error(fcinfo: pg_sys::FunctionCallInfo, code: PgSqlErrorCode, message: String, err: InvalidValue) {
+    #[cfg(any(feature = "pg16", feature = "pg17", feature = "pg18"))]
+    unsafe {
+        let context = (*fcinfo).context;
+        if !context.is_null() && pgrx::is_a(context, pg_sys::NodeTag::T_ErrorSaveContext) {
+            let escontext = context as *mut pg_sys::ErrorSaveContext;
+            (*escontext).error_occurred = true;
+
+            // Only pg_input_error_info() asks for the details
+            if (*escontext).details_wanted {
+                let edata = pg_sys::palloc0(std::mem::size_of::<pg_sys::ErrorData>()) as *mut pg_sys::ErrorData;
+                (*edata).elevel = pg_sys::ERROR as i32;
+                (*edata).sqlerrcode = code as i32;
+                (*edata).message = palloc_cstr(&message);
+                (*edata).detail = err.detail.as_deref().map_or(std::ptr::null_mut(), |d| palloc_cstr(d));
+                (*edata).hint = err.hint.as_deref().map_or(std::ptr::null_mut(), |h| palloc_cstr(h));
+                (*edata).assoc_context = pg_sys::CurrentMemoryContext;
+                (*escontext).error_data = edata;
+            }
+            return;
+        }
+    }
+
+    #[cfg(not(any(feature = "pg16", feature = "pg17", feature = "pg18")))]
+    let _ = fcinfo;
+
+    raise(err.report(code, message));
+}
+
+/// Copies `s` into a palloc'd, NUL-terminated C string.
+#[cfg(any(feature = "pg16", feature = "pg17", feature = "pg18"))]
+unsafe fn palloc_cstr(s: &str) -> *mut std::ffi::c_char {
+    let cstr = std::ffi::CString::new(s).unwrap_or_default();
+    pg_sys::pstrdup(cstr.as_ptr())
+}
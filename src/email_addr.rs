@@ -1,34 +1,577 @@
 use pgrx::prelude::*;
-use pgrx::StringInfo;
+use pgrx::pg_sys::panic::ErrorReport;
+use pgrx::datum::{FromDatum, IntoDatum};
+use pgrx::wrappers::rust_regtypein;
+use std::cell::RefCell;
 use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
 use std::str::FromStr;
 use std::fmt::{self, Display};
-use validator::ValidateEmail;
-use serde::{Deserialize, Serialize};
+use icu_normalizer::ComposingNormalizerBorrowed;
+use crate::datum::{impl_sql_type, input_typmod, report_input_error, report_modifier_error, take_message_bytes};
+use crate::config::{self, TableCache};
+use crate::email_typmod;
+use crate::email_validation::{self, Part, Violation};
+use crate::error::{raise, InvalidValue};
+use crate::public_suffix;
 
 /// An email address type that stores addresses in a validated format.
-/// Comparison is done domain-first, then local part.
-/// Case sensitivity is preserved (local part is case-sensitive, domain is case-insensitive).
-/// This matches the behavior of the original C implementation.
-/// Validation is done using the `validator` crate.
+/// Comparison is done domain-first, then local part. Domains are compared
+/// label by label starting from the TLD, so all addresses of one organisation
+/// (and its subdomains) sort next to each other.
+/// The local part is case-sensitive, the domain is case-insensitive (RFC 5321):
+/// equality, ordering and hashing fold the domain to lowercase, while the
+/// original spelling is kept for output.
+/// Internationalised domains are converted with IDNA (UTS #46) on input and
+/// compared by their ASCII (A-label) form, so `user@bücher.de` equals
+/// `user@xn--bcher-kva.de`; the local part is NFC-normalised.
+/// Input is validated against the profile selected with
+/// `pgdatatypes_plus.email_validation`, by default the rules of the `validator` crate.
 /// Note that this implementation does not handle all edge cases of email validation as per RFC 5321, but covers the vast majority of common cases.
-#[derive(Debug,  PartialEq, Eq, Serialize, Deserialize, PostgresType, PostgresEq, PostgresOrd)]
-#[inoutfuncs]
+///
+/// On disk an address is a varlena holding a format version byte, the
+/// offset of the `@` and the raw address bytes, plus the A-label domain for
+/// internationalised domains (see [`EmailAddr::encode`]), so reading a value
+/// needs no parsing.
+#[derive(Debug, PostgresEq, PostgresOrd, PostgresHash)]
 pub struct EmailAddr {
-    data: String
+    data: String,
+    /// Byte offset of the `@` separating the local part from the domain
+    at: usize,
+    /// The domain in ASCII (A-label) form if it contains non-ASCII
+    /// characters; ASCII domains compare by their lowercase spelling
+    ascii_domain: Option<String>,
 }
 
+/// Values written by 0.0.1, a serde/CBOR encoded `{"data": <address>}` map
+const FORMAT_LEGACY_CBOR: u8 = 0xa1;
+/// Version byte, `@` offset as little-endian u16, address bytes
+const FORMAT_V1: u8 = 1;
+const V1_HEADER_LEN: usize = 3;
+/// Version byte, `@` offset and address length as little-endian u16s, address
+/// bytes, A-label domain bytes
+const FORMAT_V2: u8 = 2;
+const V2_HEADER_LEN: usize = 5;
+
+/// Version byte leading the binary wire format, followed by the UTF-8 address
+const WIRE_FORMAT_V1: u8 = 1;
+
 impl FromStr for EmailAddr {
-    type Err = &'static str;
+    type Err = InvalidValue;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if !s.validate_email() {
-            return Err("invalid email address format");
+        let profile = email_validation::current_profile();
+        EmailAddr::parse(s, |s| email_validation::validate(s, profile)).map_err(InvalidValue::from)
+    }
+}
+
+impl EmailAddr {
+    /// Parses type input: unlike [`FromStr`], accepts an address valid under
+    /// any validation profile, so values stored under another profile load
+    /// back through dump/restore and binary `COPY`.
+    pub(crate) fn from_input(s: &str) -> Result<EmailAddr, InvalidValue> {
+        EmailAddr::parse(s, email_validation::validate_any).map_err(InvalidValue::from)
+    }
+
+    /// Validates `s` with `validate` and normalises it: NFC for the local
+    /// part, IDNA mapping for the domain.
+    fn parse(s: &str, validate: impl Fn(&str) -> Result<usize, Violation>) -> Result<EmailAddr, Violation> {
+        let at = validate(s)?;
+        let (local, domain) = (&s[..at], &s[at + 1..]);
+
+        let local = ComposingNormalizerBorrowed::new_nfc().normalize(local);
+        let (domain, ascii_domain) = if domain.starts_with('[') {
+            (domain.to_string(), None)
+        } else {
+            let ascii = idna::domain_to_ascii(domain).map_err(|_| {
+                Violation::new(
+                    "invalid_idn",
+                    Part::Domain,
+                    format!("\"{}\" is not a valid internationalized domain name.", domain),
+                )
+                .at(s[..at].chars().count() + 2)
+            })?;
+            if domain.is_ascii() {
+                // Keep the spelling, the A-label form is just the lowercase
+                (domain.to_string(), None)
+            } else {
+                // Mapping may turn e.g. fullwidth letters into plain ASCII
+                let unicode = idna::domain_to_unicode(&ascii).0;
+                let ascii = (!unicode.is_ascii()).then_some(ascii);
+                (unicode, ascii)
+            }
+        };
+
+        let data = format!("{}@{}", local, domain);
+        // NFC and IDNA mapping may change the address, check the result too
+        if data != s {
+            validate(&data)?;
         }
 
         Ok(EmailAddr {
-            data: s.to_string()
+            at: local.len(),
+            data,
+            ascii_domain,
+        })
+    }
+}
+
+impl EmailAddr {
+    /// Splits the address into its local part and domain.
+    fn split(&self) -> (&str, &str) {
+        (&self.data[..self.at], &self.data[self.at + 1..])
+    }
+
+    /// Builds an address from already validated `data`, working out the
+    /// A-label form of an internationalised domain.
+    pub(crate) fn from_validated(data: String, at: usize) -> EmailAddr {
+        let domain = &data[at + 1..];
+        let ascii_domain = if domain.is_ascii() {
+            None
+        } else {
+            idna::domain_to_ascii(domain).ok()
+        };
+
+        EmailAddr { data, at, ascii_domain }
+    }
+
+    /// Encodes the address in the current on-disk format. Addresses with an
+    /// ASCII domain use format 1:
+    ///
+    /// | bytes | content                                   |
+    /// |-------|-------------------------------------------|
+    /// | 0     | format version (`1`)                      |
+    /// | 1..3  | offset of the `@`, little-endian `u16`    |
+    /// | 3..   | the address as UTF-8, spelled as entered  |
+    ///
+    /// Internationalised domains use format 2, which also keeps the A-label
+    /// domain so comparisons don't have to convert it:
+    ///
+    /// | bytes      | content                                  |
+    /// |------------|------------------------------------------|
+    /// | 0          | format version (`2`)                     |
+    /// | 1..3       | offset of the `@`, little-endian `u16`   |
+    /// | 3..5       | length of the address, little-endian `u16` |
+    /// | 5..5+len   | the address as UTF-8                     |
+    /// | 5+len..    | the A-label domain                       |
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        // validation caps the local part at 64 characters, so this always fits
+        let at = u16::try_from(self.at).expect("emailaddr local part too long");
+
+        let Some(ascii_domain) = &self.ascii_domain else {
+            let mut bytes = Vec::with_capacity(V1_HEADER_LEN + self.data.len());
+            bytes.push(FORMAT_V1);
+            bytes.extend_from_slice(&at.to_le_bytes());
+            bytes.extend_from_slice(self.data.as_bytes());
+            return bytes;
+        };
+
+        // 64 characters of local part and 255 of domain fit in a u16
+        let len = u16::try_from(self.data.len()).expect("emailaddr too long");
+        let mut bytes = Vec::with_capacity(V2_HEADER_LEN + self.data.len() + ascii_domain.len());
+        bytes.push(FORMAT_V2);
+        bytes.extend_from_slice(&at.to_le_bytes());
+        bytes.extend_from_slice(&len.to_le_bytes());
+        bytes.extend_from_slice(self.data.as_bytes());
+        bytes.extend_from_slice(ascii_domain.as_bytes());
+        bytes
+    }
+
+    /// Decodes an on-disk value written in the current or any earlier format.
+    pub(crate) fn decode(bytes: &[u8]) -> Result<EmailAddr, &'static str> {
+        match bytes.first() {
+            Some(&FORMAT_V1) => {
+                let header = bytes.get(..V1_HEADER_LEN).ok_or("corrupt emailaddr value")?;
+                let at = u16::from_le_bytes([header[1], header[2]]) as usize;
+                let data = std::str::from_utf8(&bytes[V1_HEADER_LEN..])
+                    .map_err(|_| "corrupt emailaddr value")?;
+                if data.as_bytes().get(at) != Some(&b'@') {
+                    return Err("corrupt emailaddr value");
+                }
+
+                // Values written before IDNA support may have a non-ASCII domain
+                Ok(EmailAddr::from_validated(data.to_string(), at))
+            }
+            Some(&FORMAT_V2) => {
+                let header = bytes.get(..V2_HEADER_LEN).ok_or("corrupt emailaddr value")?;
+                let at = u16::from_le_bytes([header[1], header[2]]) as usize;
+                let len = u16::from_le_bytes([header[3], header[4]]) as usize;
+                let data = bytes
+                    .get(V2_HEADER_LEN..V2_HEADER_LEN + len)
+                    .and_then(|data| std::str::from_utf8(data).ok())
+                    .ok_or("corrupt emailaddr value")?;
+                let ascii_domain = std::str::from_utf8(&bytes[V2_HEADER_LEN + len..])
+                    .map_err(|_| "corrupt emailaddr value")?;
+                if data.as_bytes().get(at) != Some(&b'@') {
+                    return Err("corrupt emailaddr value");
+                }
+
+                Ok(EmailAddr {
+                    data: data.to_string(),
+                    at,
+                    ascii_domain: Some(ascii_domain.to_string()),
+                })
+            }
+            Some(&FORMAT_LEGACY_CBOR) => {
+                let data = decode_legacy_cbor(bytes).ok_or("corrupt emailaddr value")?;
+                let at = data.rfind('@').ok_or("corrupt emailaddr value")?;
+
+                Ok(EmailAddr::from_validated(data.to_string(), at))
+            }
+            _ => Err("unsupported emailaddr storage format"),
+        }
+    }
+}
+
+impl EmailAddr {
+    /// The domain as compared: the A-label form of an internationalised
+    /// domain, otherwise the domain as entered.
+    pub(crate) fn domain_key(&self) -> &str {
+        self.ascii_domain.as_deref().unwrap_or_else(|| self.split().1)
+    }
+
+    /// The bytes `emailaddr_pattern_ops` orders addresses by: the local part
+    /// as entered, the `@`, then the compared domain in lowercase, so that
+    /// addresses equal under `=` have equal bytes.
+    pub(crate) fn pattern_bytes(&self) -> impl Iterator<Item = u8> + '_ {
+        self.local()
+            .bytes()
+            .chain(std::iter::once(b'@'))
+            .chain(self.domain_key().bytes().map(|c| c.to_ascii_lowercase()))
+    }
+
+    /// The local part, spelled as entered, including any quotes and `+tag`.
+    pub(crate) fn local(&self) -> &str {
+        self.split().0
+    }
+
+    /// The domain in lowercase, including IP address literals such as
+    /// `[127.0.0.1]`.
+    pub(crate) fn domain(&self) -> String {
+        self.split().1.to_ascii_lowercase()
+    }
+
+    /// The last label of the domain in lowercase. IP addresses, bracketed or
+    /// not, have no top-level domain.
+    pub(crate) fn tld(&self) -> Option<String> {
+        let domain = self.split().1;
+        if domain.starts_with('[') {
+            return None;
+        }
+
+        // TLDs are never all-numeric (RFC 3696, section 2)
+        let tld = domain.rsplit('.').next()?;
+        if tld.bytes().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        Some(tld.to_ascii_lowercase())
+    }
+
+    /// The subaddress (`tag` in `user+tag@example.com`): everything after
+    /// the first `+` of the local part. A `+` inside a quoted string does
+    /// not start a subaddress.
+    pub(crate) fn subaddress(&self) -> Option<&str> {
+        self.split_subaddress('+').1
+    }
+
+    /// Splits the local part at the first unquoted `separator` into the
+    /// mailbox and the subaddress.
+    pub(crate) fn split_subaddress(&self, separator: char) -> (&str, Option<&str>) {
+        let local = self.local();
+        let mut quoted = false;
+        let mut escaped = false;
+        for (i, c) in local.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if quoted => escaped = true,
+                '"' => quoted = !quoted,
+                c if c == separator && !quoted => {
+                    return (&local[..i], Some(&local[i + c.len_utf8()..]))
+                }
+                _ => {}
+            }
+        }
+        (local, None)
+    }
+}
+
+/// How addresses at one provider's domain are canonicalised, see
+/// `emailaddr_canonical_rules`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CanonicalRule {
+    /// The domain the provider's aliases map to
+    canonical_domain: Option<String>,
+    strip_dots: bool,
+    strip_subaddress: bool,
+    subaddress_separator: char,
+    lowercase_local: bool,
+}
+
+/// Rules that apply unless `emailaddr_canonical_rules` has a row for the
+/// domain. Gmail ignores dots and case in the local part, and
+/// `googlemail.com` is an alias of `gmail.com`.
+pub(crate) fn builtin_canonical_rule(domain: &str) -> Option<CanonicalRule> {
+    match domain {
+        "gmail.com" | "googlemail.com" => Some(CanonicalRule {
+            canonical_domain: Some("gmail.com".to_string()),
+            strip_dots: true,
+            strip_subaddress: true,
+            subaddress_separator: '+',
+            lowercase_local: true,
+        }),
+        _ => None,
+    }
+}
+
+thread_local! {
+    static CANONICAL_RULES: TableCache<HashMap<String, CanonicalRule>> = const { RefCell::new(None) };
+}
+
+/// The rows of `emailaddr_canonical_rules` by domain, cached per backend
+/// until the table changes.
+fn canonical_rules() -> Rc<HashMap<String, CanonicalRule>> {
+    config::cached_table(&CANONICAL_RULES, "emailaddr_canonical_rules", |table| {
+        let query = format!(
+            "SELECT domain, canonical_domain, strip_dots, strip_subaddress, subaddress_separator, lowercase_local \
+             FROM {}",
+            table
+        );
+        let mut rules = HashMap::new();
+        Spi::connect(|client| {
+            for row in client.select(&query, None, &[])? {
+                let Some(domain) = row.get::<String>(1)? else {
+                    continue;
+                };
+                let separator = row.get::<String>(5)?.unwrap_or_default();
+                rules.insert(domain, CanonicalRule {
+                    canonical_domain: row.get::<String>(2)?,
+                    strip_dots: row.get::<bool>(3)?.unwrap_or(false),
+                    strip_subaddress: row.get::<bool>(4)?.unwrap_or(false),
+                    subaddress_separator: separator.chars().next().unwrap_or('+'),
+                    lowercase_local: row.get::<bool>(6)?.unwrap_or(false),
+                });
+            }
+            Ok::<_, pgrx::spi::Error>(())
         })
+        .unwrap_or_else(|e| error!("could not read {}: {}", table, e));
+        rules
+    })
+}
+
+/// Looks up the rule for a lowercase `domain`: a row of
+/// `emailaddr_canonical_rules`, otherwise the built-in rule.
+fn canonical_rule(domain: &str) -> Option<CanonicalRule> {
+    match canonical_rules().get(domain) {
+        Some(rule) => Some(rule.clone()),
+        None => builtin_canonical_rule(domain),
+    }
+}
+
+impl EmailAddr {
+    /// The canonical form of the address: the domain in lowercase, with
+    /// `rule` applied if there is one. Falls back to only lowercasing the
+    /// domain when the rule would leave the local part empty.
+    ///
+    /// The result is not revalidated, so it doesn't depend on the
+    /// validation profile in effect.
+    pub(crate) fn canonicalize(&self, rule: Option<&CanonicalRule>) -> EmailAddr {
+        let domain = self.domain();
+        let lowercased = || EmailAddr::from_validated(format!("{}@{}", self.local(), domain), self.at);
+        let Some(rule) = rule else {
+            return lowercased();
+        };
+
+        let mut local = if rule.strip_subaddress {
+            self.split_subaddress(rule.subaddress_separator).0.to_string()
+        } else {
+            self.local().to_string()
+        };
+        if rule.strip_dots {
+            local.retain(|c| c != '.');
+        }
+        if rule.lowercase_local {
+            local = local.to_lowercase();
+        }
+        if local.is_empty() {
+            return lowercased();
+        }
+        let domain = rule.canonical_domain.as_deref().unwrap_or(&domain);
+
+        EmailAddr::from_validated(format!("{}@{}", local, domain), local.len())
+    }
+
+    /// The canonical form of the address under the rules in effect.
+    pub(crate) fn canonical(&self) -> EmailAddr {
+        self.canonicalize(canonical_rule(&self.domain()).as_ref())
+    }
+
+    /// The canonical form of the address under the built-in rules only.
+    pub(crate) fn canonical_builtin(&self) -> EmailAddr {
+        self.canonicalize(builtin_canonical_rule(&self.domain()).as_ref())
+    }
+}
+
+impl EmailAddr {
+    /// The address with its domain in ASCII (A-label) form, as needed by mail
+    /// servers without SMTPUTF8 support.
+    fn to_ascii(self) -> EmailAddr {
+        match &self.ascii_domain {
+            Some(ascii_domain) => EmailAddr {
+                data: format!("{}@{}", self.local(), ascii_domain),
+                at: self.at,
+                ascii_domain: None,
+            },
+            None => self,
+        }
+    }
+
+    /// The address with A-labels (`xn--...`) in its domain decoded to Unicode.
+    fn to_unicode(self) -> EmailAddr {
+        let domain = self.split().1;
+        let has_a_label = domain
+            .split('.')
+            .any(|label| label.len() >= 4 && label[..4].eq_ignore_ascii_case("xn--"));
+        if self.ascii_domain.is_some() || !has_a_label {
+            return self;
+        }
+
+        let unicode = idna::domain_to_unicode(domain).0;
+        EmailAddr::from_validated(format!("{}@{}", self.local(), unicode), self.at)
+    }
+}
+
+impl EmailAddr {
+    /// The public suffix of the domain (`co.uk` for `mail.example.co.uk`)
+    /// according to the Public Suffix List in effect. IP addresses have none.
+    fn public_suffix(&self) -> Option<String> {
+        self.tld()?;
+        // The list spells internationalised domains in Unicode
+        let domain = idna::domain_to_unicode(self.domain_key()).0;
+        Some(public_suffix::current_list().public_suffix(&domain).to_string())
+    }
+
+    /// The domain registered under the public suffix (`example.co.uk` for
+    /// `mail.example.co.uk`). Domains that are themselves a public suffix
+    /// and IP addresses have none.
+    fn registrable_domain(&self) -> Option<String> {
+        self.tld()?;
+        let domain = idna::domain_to_unicode(self.domain_key()).0;
+        public_suffix::current_list()
+            .registrable_domain(&domain)
+            .map(str::to_string)
+    }
+}
+
+/// The domain argument of `<@` in the form domains are compared in: the
+/// lowercase A-label form, without a trailing dot.
+pub(crate) fn domain_argument(domain: &str) -> Result<String, InvalidValue> {
+    let trimmed = domain.strip_suffix('.').unwrap_or(domain);
+    let invalid = || {
+        InvalidValue::new("invalid domain")
+            .with_detail(format!("\"{}\" is not a valid domain name.", domain))
+    };
+
+    if trimmed.is_empty() {
+        return Err(invalid());
+    }
+    if trimmed.starts_with('[') {
+        return Ok(trimmed.to_ascii_lowercase());
+    }
+    idna::domain_to_ascii(trimmed).map_err(|_| invalid())
+}
+
+impl EmailAddr {
+    /// The bounds of the addresses at `domain` and its subdomains, which the
+    /// domain-first ordering keeps together: from an empty local part at
+    /// `domain` (below every address there) up to, but excluding, a
+    /// subdomain label that sorts after every real one.
+    fn domain_range(domain: &str) -> Result<(EmailAddr, EmailAddr), InvalidValue> {
+        let domain = domain_argument(domain)?;
+        let start = EmailAddr { data: format!("@{}", domain), at: 0, ascii_domain: None };
+
+        // Stored with its compared form, so decoding doesn't run it through IDNA
+        let past = format!("{}.{}", char::MAX, domain);
+        let end = EmailAddr { data: format!("@{}", past), at: 0, ascii_domain: Some(past) };
+        Ok((start, end))
+    }
+
+    /// Whether the address is at `domain`, given as from
+    /// [`domain_argument`], or one of its subdomains, like `<@`.
+    pub(crate) fn in_domain(&self, domain: &str) -> bool {
+        let key = self.domain_key().to_ascii_lowercase();
+        key == domain
+            || (!domain.starts_with('[') && key.strip_suffix(domain).is_some_and(|sub| sub.ends_with('.')))
+    }
+}
+
+impl EmailAddr {
+    /// Encodes the address in the binary wire format used by `emailaddr_send`:
+    /// the version byte `1` followed by the address as UTF-8, spelled as
+    /// entered. The format is stable across releases.
+    fn to_wire(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(1 + self.data.len());
+        bytes.push(WIRE_FORMAT_V1);
+        bytes.extend_from_slice(self.data.as_bytes());
+        bytes
+    }
+
+    /// Decodes and validates a value in the binary wire format.
+    fn from_wire(bytes: &[u8]) -> Result<EmailAddr, InvalidValue> {
+        match bytes.split_first() {
+            Some((&WIRE_FORMAT_V1, address)) => {
+                let address = std::str::from_utf8(address).map_err(|e| {
+                    InvalidValue::new("invalid UTF-8 in email input").with_detail(e.to_string())
+                })?;
+                EmailAddr::from_input(address)
+            }
+            Some((version, _)) => Err(InvalidValue::new("unsupported emailaddr binary format version")
+                .with_detail(format!("Got version {}, only version {} is supported.", version, WIRE_FORMAT_V1))),
+            None => Err(InvalidValue::new("missing emailaddr binary format version")),
+        }
+    }
+}
+
+/// Compares two domains label by label from the TLD inward, so that
+/// `example.com` < `mail.example.com` < `example.org`.
+/// Labels are compared ignoring ASCII case.
+fn cmp_domains(a: &str, b: &str) -> Ordering {
+    let mut a_labels = a.rsplit('.');
+    let mut b_labels = b.rsplit('.');
+    loop {
+        match (a_labels.next(), b_labels.next()) {
+            (Some(a_label), Some(b_label)) => match cmp_ignore_ascii_case(a_label, b_label) {
+                Ordering::Equal => continue,
+                ord => return ord,
+            },
+            (Some(_), None) => return Ordering::Greater,
+            (None, Some(_)) => return Ordering::Less,
+            (None, None) => return Ordering::Equal,
+        }
+    }
+}
+
+fn cmp_ignore_ascii_case(a: &str, b: &str) -> Ordering {
+    let a = a.bytes().map(|c| c.to_ascii_lowercase());
+    let b = b.bytes().map(|c| c.to_ascii_lowercase());
+    a.cmp(b)
+}
+
+impl PartialEq for EmailAddr {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for EmailAddr {}
+
+// Must agree with `Eq`: the domain is hashed in its lowercase A-label form
+impl Hash for EmailAddr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.local().hash(state);
+        self.domain_key().to_ascii_lowercase().hash(state);
     }
 }
 
@@ -42,7 +585,8 @@ impl PartialOrd for EmailAddr {
 impl Ord for EmailAddr {
     fn cmp(&self, other: &Self) -> Ordering {
         // If domains are equal, compare local parts
-        self.data.cmp(&other.data)
+        cmp_domains(self.domain_key(), other.domain_key())
+            .then_with(|| self.local().cmp(other.local()))
     }
 }
 
@@ -52,22 +596,119 @@ impl Display for EmailAddr {
     }
 }
 
-impl InOutFuncs for EmailAddr {
-    fn input(input: &std::ffi::CStr) -> EmailAddr {
-        let input_str = input.to_str().unwrap_or_else(|e| {
-            error!("invalid UTF-8 in email input: {}", e);
-        });
-        
-        EmailAddr::from_str(input_str).unwrap_or_else(|e| {
-            error!("invalid input syntax for type emailaddr: {}", e);
-        })
+impl FromDatum for EmailAddr {
+    unsafe fn from_polymorphic_datum(datum: pg_sys::Datum, is_null: bool, typoid: pg_sys::Oid) -> Option<Self> {
+        let bytes = <&[u8]>::from_polymorphic_datum(datum, is_null, typoid)?;
+
+        Some(EmailAddr::decode(bytes).unwrap_or_else(|e| {
+            ereport!(ERROR, PgSqlErrorCode::ERRCODE_DATA_CORRUPTED, e);
+        }))
     }
+}
 
-    fn output(&self, buffer: &mut StringInfo) {
-        buffer.push_str(&self.data);
+impl IntoDatum for EmailAddr {
+    fn into_datum(self) -> Option<pg_sys::Datum> {
+        self.encode().into_datum()
+    }
+
+    fn type_oid() -> pg_sys::Oid {
+        rust_regtypein::<Self>()
     }
 }
 
+impl_sql_type!(EmailAddr, "emailaddr");
+
+extension_sql!(
+    r#"
+CREATE TYPE emailaddr;
+
+CREATE FUNCTION emailaddr_in(cstring) RETURNS emailaddr
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_in_wrapper';
+
+CREATE FUNCTION emailaddr_out(emailaddr) RETURNS cstring
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_out_wrapper';
+
+CREATE FUNCTION emailaddr_recv(internal) RETURNS emailaddr
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_recv_wrapper';
+
+-- Not PARALLEL SAFE: it may add the domain of a type modifier to
+-- emailaddr_typmod_domains. IMMUTABLE as a modifier always maps to the
+-- same typmod.
+CREATE FUNCTION emailaddr_typmod_in(cstring[]) RETURNS integer
+    IMMUTABLE STRICT
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_typmod_in_wrapper';
+
+CREATE FUNCTION emailaddr_typmod_out(integer) RETURNS cstring
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_typmod_out_wrapper';
+
+CREATE FUNCTION emailaddr_send(emailaddr) RETURNS bytea
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'emailaddr_send_wrapper';
+
+CREATE TYPE emailaddr (
+    INTERNALLENGTH = variable,
+    INPUT = emailaddr_in,
+    OUTPUT = emailaddr_out,
+    RECEIVE = emailaddr_recv,
+    SEND = emailaddr_send,
+    TYPMOD_IN = emailaddr_typmod_in,
+    TYPMOD_OUT = emailaddr_typmod_out,
+    STORAGE = extended
+);
+"#,
+    name = "emailaddr_type",
+    creates = [Type(EmailAddr)],
+);
+
+/// Declared with one argument; the type modifier PostgreSQL passes as the
+/// third is read from `fcinfo`.
+#[pg_extern(immutable, parallel_safe, sql = false)]
+fn emailaddr_in(input: &CStr, fcinfo: pg_sys::FunctionCallInfo) -> Option<EmailAddr> {
+    let parsed = match input.to_str() {
+        Ok(input_str) => EmailAddr::from_input(input_str),
+        Err(e) => Err(InvalidValue::new("invalid UTF-8 in email input").with_detail(e.to_string())),
+    };
+
+    match parsed {
+        Ok(email) => match email_typmod::check(&email, input_typmod(fcinfo)) {
+            Ok(()) => Some(email),
+            Err(violation) => {
+                report_modifier_error(fcinfo, violation.code(), violation.into());
+                None
+            }
+        },
+        Err(e) => {
+            report_input_error(fcinfo, "emailaddr", e);
+            None
+        }
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, sql = false)]
+fn emailaddr_out(input: EmailAddr) -> CString {
+    // validated addresses never contain NUL
+    CString::new(input.data).unwrap()
+}
+
+#[pg_extern(immutable, parallel_safe, sql = false)]
+fn emailaddr_recv(mut internal: Internal, fcinfo: pg_sys::FunctionCallInfo) -> EmailAddr {
+    let buf = unsafe { internal.get_mut::<pg_sys::StringInfoData>() }.unwrap();
+    let bytes = unsafe { take_message_bytes(buf) };
+
+    let email = EmailAddr::from_wire(bytes).unwrap_or_else(|e| raise(e.binary_input_report("emailaddr")));
+    email_typmod::check(&email, input_typmod(fcinfo)).unwrap_or_else(|violation| raise(violation.report()));
+    email
+}
+
+#[pg_extern(immutable, parallel_safe, sql = false)]
+fn emailaddr_send(input: EmailAddr) -> Vec<u8> {
+    input.to_wire()
+}
+
 /// Cast EmailAddr to text
 #[pg_cast(assignment)]
 fn cast_emailaddr_to_text(input: EmailAddr) -> String {
@@ -75,22 +716,201 @@ fn cast_emailaddr_to_text(input: EmailAddr) -> String {
 }
 
 /// Cast text to EmailAddr
-#[pg_cast(assignment)]
+#[pg_cast(assignment, stable, parallel_safe)]
 fn cast_text_to_emailaddr(input: &str) -> EmailAddr {
-    EmailAddr::from_str(input).unwrap_or_else(|e| {
-        error!("invalid input syntax for type emailaddr: {}", e);
-    })
+    EmailAddr::from_str(input).unwrap_or_else(|e| raise(e.input_report("emailaddr")))
 }
 
 
 /// Create an email address from a text string
-#[pg_extern(immutable, parallel_safe)]
+#[pg_extern(stable, parallel_safe)]
 fn emailaddr(input: &str) -> EmailAddr {
-    EmailAddr::from_str(input).unwrap_or_else(|e| {
-        error!("invalid input syntax for type emailaddr: {}", e);
-    })
+    EmailAddr::from_str(input).unwrap_or_else(|e| raise(e.input_report("emailaddr")))
+}
+
+/// The local part of the address, including any `+tag`
+#[pg_extern(immutable, parallel_safe)]
+fn emailaddr_local(input: EmailAddr) -> String {
+    input.local().to_string()
+}
+
+/// The domain of the address in lowercase
+#[pg_extern(immutable, parallel_safe)]
+fn emailaddr_domain(input: EmailAddr) -> String {
+    input.domain()
+}
+
+/// The top-level domain of the address, NULL for IP address domains
+#[pg_extern(immutable, parallel_safe)]
+fn emailaddr_tld(input: EmailAddr) -> Option<String> {
+    input.tld()
+}
+
+/// The subaddress after the first `+` of the local part, NULL if there is none
+#[pg_extern(immutable, parallel_safe)]
+fn emailaddr_subaddress(input: EmailAddr) -> Option<String> {
+    input.subaddress().map(str::to_string)
+}
+
+/// The public suffix of the address's domain, NULL for IP address domains
+#[pg_extern(stable, parallel_safe)]
+fn emailaddr_public_suffix(input: EmailAddr) -> Option<String> {
+    input.public_suffix()
+}
+
+/// The registrable domain of the address (the public suffix plus one label),
+/// NULL if the domain is a public suffix itself or an IP address
+#[pg_extern(stable, parallel_safe)]
+fn emailaddr_registrable_domain(input: EmailAddr) -> Option<String> {
+    input.registrable_domain()
+}
+
+/// Whether both addresses belong to the same registrable domain. Addresses
+/// without one only match when their domains are equal.
+#[pg_extern(stable, parallel_safe)]
+fn emailaddr_same_organization(a: EmailAddr, b: EmailAddr) -> bool {
+    match (a.registrable_domain(), b.registrable_domain()) {
+        (Some(a), Some(b)) => a == b,
+        _ => a.domain() == b.domain(),
+    }
+}
+
+/// The lowest address at `domain` or its subdomains, for `<@`. Not a valid
+/// address itself.
+#[pg_extern(immutable, parallel_safe)]
+fn emailaddr_domain_range_start(domain: &str) -> Result<EmailAddr, ErrorReport> {
+    let (start, _) = EmailAddr::domain_range(domain).map_err(InvalidValue::parameter_report)?;
+    Ok(start)
+}
+
+/// The address just past every address at `domain` or its subdomains, for
+/// `<@`. Not a valid address itself.
+#[pg_extern(immutable, parallel_safe)]
+fn emailaddr_domain_range_end(domain: &str) -> Result<EmailAddr, ErrorReport> {
+    let (_, end) = EmailAddr::domain_range(domain).map_err(InvalidValue::parameter_report)?;
+    Ok(end)
 }
 
+extension_sql!(
+    r#"
+-- email <@ 'example.com': the address is at example.com or a subdomain of it.
+-- A SQL function so the planner inlines it into a range over the
+-- domain-first ordering, which a btree index on the column can serve.
+-- Not STRICT, which would keep it from being inlined.
+CREATE FUNCTION emailaddr_in_domain(emailaddr, text) RETURNS boolean
+    IMMUTABLE PARALLEL SAFE
+    LANGUAGE sql
+    AS 'SELECT $1 OPERATOR(@extschema@.>=) @extschema@.emailaddr_domain_range_start($2)
+           AND $1 OPERATOR(@extschema@.<) @extschema@.emailaddr_domain_range_end($2)';
+
+CREATE OPERATOR <@ (
+    LEFTARG = emailaddr,
+    RIGHTARG = text,
+    FUNCTION = emailaddr_in_domain
+);
+"#,
+    name = "emailaddr_in_domain",
+    requires = [emailaddr_domain_range_start, emailaddr_domain_range_end, emailaddr_lt, emailaddr_ge],
+);
+
+extension_sql!(
+    r#"
+CREATE TYPE emailaddr_validation_result AS (
+    valid boolean,
+    reason_code text,
+    message text,
+    "position" integer,
+    part text
+);
+"#,
+    name = "emailaddr_validation_result",
+);
+
+/// Checks `input` under the current validation profile. For an invalid
+/// address, tells why: a stable reason code, a message, and the 1-based
+/// character position and part (`local_part`, `domain` or `address`) the
+/// problem was found in.
+#[pg_extern(stable, parallel_safe, requires = ["emailaddr_validation_result"])]
+fn emailaddr_validate(input: &str) -> pgrx::composite_type!('static, "emailaddr_validation_result") {
+    let mut result = PgHeapTuple::new_composite_type("emailaddr_validation_result").unwrap();
+
+    let profile = email_validation::current_profile();
+    match EmailAddr::parse(input, |s| email_validation::validate(s, profile)) {
+        Ok(_) => result.set_by_name("valid", true).unwrap(),
+        Err(violation) => {
+            result.set_by_name("valid", false).unwrap();
+            result.set_by_name("reason_code", violation.code).unwrap();
+            result.set_by_name("message", violation.message).unwrap();
+            result.set_by_name("position", violation.position.map(|p| p as i32)).unwrap();
+            result.set_by_name("part", violation.part.as_str()).unwrap();
+        }
+    }
+    result
+}
+
+/// The address with an internationalised domain in ASCII (A-label) form
+#[pg_extern(immutable, parallel_safe)]
+fn emailaddr_to_ascii(input: EmailAddr) -> EmailAddr {
+    input.to_ascii()
+}
+
+/// The address with A-labels in the domain decoded to Unicode
+#[pg_extern(immutable, parallel_safe)]
+fn emailaddr_to_unicode(input: EmailAddr) -> EmailAddr {
+    input.to_unicode()
+}
+
+/// The canonical form of the address, under which all spellings of one
+/// mailbox are equal: `J.O.H.N+promo@googlemail.com` becomes
+/// `john@gmail.com`. `STABLE`, as the rules can change.
+#[pg_extern(stable, parallel_safe)]
+fn emailaddr_canonical(input: EmailAddr) -> EmailAddr {
+    input.canonical()
+}
+
+/// The canonical form of the address under the built-in provider rules,
+/// ignoring `emailaddr_canonical_rules`. `IMMUTABLE`, so it can back an
+/// expression index or a unique index on the canonical form.
+#[pg_extern(immutable, parallel_safe)]
+fn emailaddr_canonical_builtin(input: EmailAddr) -> EmailAddr {
+    input.canonical_builtin()
+}
+
+extension_sql!(
+    r#"
+-- Canonicalisation rules per provider domain, overriding the built-in rules
+CREATE TABLE emailaddr_canonical_rules (
+    domain text PRIMARY KEY CHECK (domain = lower(domain)),
+    canonical_domain text CHECK (canonical_domain = lower(canonical_domain)),
+    strip_dots boolean NOT NULL DEFAULT false,
+    strip_subaddress boolean NOT NULL DEFAULT false,
+    subaddress_separator text NOT NULL DEFAULT '+' CHECK (length(subaddress_separator) = 1),
+    lowercase_local boolean NOT NULL DEFAULT false
+);
+SELECT pg_catalog.pg_extension_config_dump('emailaddr_canonical_rules', '');
+
+CREATE TRIGGER emailaddr_canonical_rules_changed
+    AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON emailaddr_canonical_rules
+    FOR EACH STATEMENT EXECUTE FUNCTION pgdatatypes_plus_config_changed();
+
+CREATE FUNCTION emailaddr_equivalent(emailaddr, emailaddr) RETURNS boolean
+    STABLE STRICT PARALLEL SAFE
+    LANGUAGE sql
+    AS 'SELECT @extschema@.emailaddr_canonical($1) OPERATOR(@extschema@.=) @extschema@.emailaddr_canonical($2)';
+
+CREATE OPERATOR ~= (
+    LEFTARG = emailaddr,
+    RIGHTARG = emailaddr,
+    FUNCTION = emailaddr_equivalent,
+    COMMUTATOR = ~=,
+    RESTRICT = eqsel,
+    JOIN = eqjoinsel
+);
+"#,
+    name = "emailaddr_canonical_equivalence",
+    requires = [emailaddr_canonical, emailaddr_eq, crate::config::pgdatatypes_plus_config_changed],
+);
+
 #[cfg(any(test, feature = "pg_test"))]
 #[pg_schema]
 mod tests {
@@ -106,6 +926,48 @@ mod tests {
         let email3 = EmailAddr::from_str("aaa@same.com").unwrap();
         let email4 = EmailAddr::from_str("zzz@same.com").unwrap();
         assert!(email3 < email4);
+
+        // The domain decides before the local part does
+        let email5 = EmailAddr::from_str("zzz@a.com").unwrap();
+        let email6 = EmailAddr::from_str("aaa@b.com").unwrap();
+        assert!(email5 < email6);
+    }
+
+    #[pg_test]
+    fn test_domain_labels_compared_from_tld() {
+        let mut emails: Vec<EmailAddr> = [
+            "a@example.org",
+            "b@mail.example.com",
+            "c@example.com",
+            "d@zzz.com",
+            "e@aaa.net",
+        ]
+        .iter()
+        .map(|s| EmailAddr::from_str(s).unwrap())
+        .collect();
+        emails.sort();
+
+        let sorted: Vec<String> = emails.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            sorted,
+            vec![
+                "c@example.com",
+                "b@mail.example.com",
+                "d@zzz.com",
+                "e@aaa.net",
+                "a@example.org",
+            ]
+        );
+    }
+
+    #[pg_test]
+    fn test_order_by_uses_domain_first_ordering() {
+        let result = Spi::get_one::<String>(
+            "SELECT string_agg(e::text, ',' ORDER BY e) FROM (VALUES \
+                ('a@example.org'::emailaddr), ('b@mail.example.com'), ('z@example.com')) v(e)"
+        ).expect("SPI result should not be NULL").unwrap();
+
+        assert_eq!(result, "z@example.com,b@mail.example.com,a@example.org");
     }
 
     #[pg_test]
@@ -115,33 +977,489 @@ mod tests {
         assert!(EmailAddr::from_str("user@").is_err());
     }
 
+    #[pg_test]
+    fn test_invalid_email_details() {
+        let detail = |s: &str| EmailAddr::from_str(s).unwrap_err().detail;
+
+        assert_eq!(
+            detail("abc").as_deref(),
+            Some("The address has no \"@\" separating the local part from the domain.")
+        );
+        assert_eq!(
+            detail("a b@x.cz").as_deref(),
+            Some("The local part contains the character \" \" at position 2, which is not allowed.")
+        );
+        assert_eq!(
+            detail("John.Doe@exam_ple.com").as_deref(),
+            Some("The domain contains the character \"_\" at position 14, which is not allowed.")
+        );
+        assert_eq!(detail("trailingdot@shouldfail.com.").as_deref(), Some("The domain ends with a dot."));
+        assert_eq!(
+            detail("example@invalid-.com").as_deref(),
+            Some("The domain label \"invalid-\" starts or ends with a hyphen.")
+        );
+        assert_eq!(
+            detail("email@[127.0.0.256]").as_deref(),
+            Some("\"[127.0.0.256]\" is not a valid IP address literal.")
+        );
+
+        let err = EmailAddr::from_str("a@b.com\n").unwrap_err();
+        assert_eq!(err.reason, "invalid email address format");
+        assert!(err.detail.unwrap().contains("\\n"));
+    }
+
+    #[pg_test]
+    fn test_invalid_input_sqlstate() {
+        Spi::run(
+            "CREATE FUNCTION pg_temp.sqlstate_of(input text) RETURNS text LANGUAGE plpgsql AS $$ \
+             BEGIN PERFORM input::emailaddr; RETURN NULL; \
+             EXCEPTION WHEN OTHERS THEN RETURN SQLSTATE; END $$"
+        ).unwrap();
+
+        let sqlstate = Spi::get_one::<String>("SELECT pg_temp.sqlstate_of('not-an-email')")
+            .expect("SPI result should not be NULL").unwrap();
+        assert_eq!(sqlstate, "22P02");
+    }
+
     #[pg_test]
     fn test_case_sensitivity() {
-        // Email addresses should be case-insensitive for domain, case-sensitive for localcar
-        // For simplicity, our implementation treats them as case-sensitive
-        // This matches the original C implementation behavior
-        let email1 = EmailAddr::from_str("User@Domain.Com").unwrap();
+        // The domain is case-insensitive, the local part is case-sensitive
+        let email1 = EmailAddr::from_str("user@Domain.Com").unwrap();
         let email2 = EmailAddr::from_str("user@domain.com").unwrap();
-        
-        // Different case = different emails in our implementation
-        assert_ne!(email1, email2);
+        assert_eq!(email1, email2);
+        assert_eq!(email1.cmp(&email2), Ordering::Equal);
+
+        let email3 = EmailAddr::from_str("User@Domain.Com").unwrap();
+        assert_ne!(email3, email2);
+
+        // The original spelling is preserved for output
+        assert_eq!(email1.to_string(), "user@Domain.Com");
     }
 
     #[pg_test]
-    fn test_serialization() {
-        let email = EmailAddr::from_str("test@example.com").unwrap();
-        
-        // Test that serialization/deserialization works (pgrx uses this internally)
-        let serialized = serde_json::to_string(&email).unwrap();
-        let deserialized: EmailAddr = serde_json::from_str(&serialized).unwrap();
-        
-        assert_eq!(email, deserialized);
+    fn test_hash_folds_domain_case() {
+        use std::collections::hash_map::DefaultHasher;
+
+        fn hash_of(email: &EmailAddr) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            email.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let email1 = EmailAddr::from_str("user@EXAMPLE.com").unwrap();
+        let email2 = EmailAddr::from_str("user@example.COM").unwrap();
+        assert_eq!(hash_of(&email1), hash_of(&email2));
     }
 
-        #[test]
-    fn test_validate_email() {
+    #[pg_test]
+    fn test_hash_opclass() {
+        let same = Spi::get_one::<bool>(
+            "SELECT emailaddr_hash('user@EXAMPLE.com') = emailaddr_hash('user@example.COM')"
+        ).expect("SPI result should not be NULL").unwrap();
+        assert!(same);
+
+        Spi::run("CREATE TABLE hash_emails (email emailaddr)").unwrap();
+        Spi::run("CREATE INDEX hash_emails_idx ON hash_emails USING hash (email)").unwrap();
+        Spi::run("INSERT INTO hash_emails VALUES ('a@Example.com'), ('b@example.com')").unwrap();
+
+        let count = Spi::get_one::<i64>(
+            "SELECT count(*) FROM hash_emails WHERE email = 'a@example.COM'"
+        ).expect("SPI result should not be NULL").unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[pg_test]
+    fn test_accessors() {
+        let email = EmailAddr::from_str("John.Doe+news@Mail.Example.COM").unwrap();
+        assert_eq!(email.local(), "John.Doe+news");
+        assert_eq!(email.domain(), "mail.example.com");
+        assert_eq!(email.tld().as_deref(), Some("com"));
+        assert_eq!(email.subaddress(), Some("news"));
+
+        let email = EmailAddr::from_str("user@localhost").unwrap();
+        assert_eq!(email.tld().as_deref(), Some("localhost"));
+        assert_eq!(email.subaddress(), None);
+
+        // Only the first `+` starts the subaddress, and it may be empty
+        assert_eq!(EmailAddr::from_str("a+b+c@x.com").unwrap().subaddress(), Some("b+c"));
+        assert_eq!(EmailAddr::from_str("a+@x.com").unwrap().subaddress(), Some(""));
+
+        // IP addresses have no TLD
+        let email = EmailAddr::from_str("user@[127.0.0.1]").unwrap();
+        assert_eq!(email.domain(), "[127.0.0.1]");
+        assert_eq!(email.tld(), None);
+        assert_eq!(EmailAddr::from_str("user@[2001:dB8::1]").unwrap().domain(), "[2001:db8::1]");
+        assert_eq!(EmailAddr::from_str("user@127.0.0.1").unwrap().tld(), None);
+    }
+
+    #[test]
+    fn test_accessors_quoted_local_part() {
+        let data = r#""john+doe@home"+work@example.com"#;
+        let email = EmailAddr::from_validated(data.to_string(), data.rfind('@').unwrap());
+        assert_eq!(email.local(), r#""john+doe@home"+work"#);
+        assert_eq!(email.domain(), "example.com");
+        assert_eq!(email.subaddress(), Some("work"));
+
+        let data = r#""a\"+b"@example.com"#;
+        let email = EmailAddr::from_validated(data.to_string(), data.rfind('@').unwrap());
+        assert_eq!(email.subaddress(), None);
+    }
 
-        let tests = vec![
+    #[pg_test]
+    fn test_accessor_functions() {
+        let row = Spi::get_three::<String, String, String>(
+            "SELECT emailaddr_local(e), emailaddr_domain(e), emailaddr_tld(e) \
+             FROM (SELECT 'User+tag@Example.ORG'::emailaddr AS e) t"
+        ).unwrap();
+        assert_eq!(row, (Some("User+tag".to_string()), Some("example.org".to_string()), Some("org".to_string())));
+
+        let subaddress = Spi::get_one::<String>("SELECT emailaddr_subaddress('user+tag@example.org')")
+            .unwrap();
+        assert_eq!(subaddress.as_deref(), Some("tag"));
+
+        let subaddress = Spi::get_one::<String>("SELECT emailaddr_subaddress('user@example.org')")
+            .unwrap();
+        assert_eq!(subaddress, None);
+
+        let tld = Spi::get_one::<String>("SELECT emailaddr_tld('user@[10.0.0.1]')").unwrap();
+        assert_eq!(tld, None);
+    }
+
+    #[pg_test]
+    fn test_registrable_domain() {
+        let email = EmailAddr::from_str("ceo@mail.corp.Example.CO.UK").unwrap();
+        assert_eq!(email.public_suffix().as_deref(), Some("co.uk"));
+        assert_eq!(email.registrable_domain().as_deref(), Some("example.co.uk"));
+
+        let email = EmailAddr::from_str("admin@co.uk").unwrap();
+        assert_eq!(email.public_suffix().as_deref(), Some("co.uk"));
+        assert_eq!(email.registrable_domain(), None);
+
+        let email = EmailAddr::from_str("root@[127.0.0.1]").unwrap();
+        assert_eq!(email.public_suffix(), None);
+        assert_eq!(email.registrable_domain(), None);
+    }
+
+    #[pg_test]
+    fn test_same_organization() {
+        let same = |a: &str, b: &str| {
+            Spi::get_one_with_args::<bool>(
+                "SELECT emailaddr_same_organization($1::emailaddr, $2::emailaddr)",
+                &[a.into(), b.into()],
+            ).expect("SPI result should not be NULL").unwrap()
+        };
+
+        assert!(same("a@mail.corp.example.co.uk", "b@example.co.uk"));
+        assert!(same("a@Example.com", "b@shop.example.com"));
+        assert!(!same("a@example.co.uk", "b@other.co.uk"));
+        assert!(!same("a@alice.github.io", "b@bob.github.io"));
+        assert!(same("a@[127.0.0.1]", "b@[127.0.0.1]"));
+        assert!(!same("a@[127.0.0.1]", "b@[127.0.0.2]"));
+
+        let domain = Spi::get_one::<String>(
+            "SELECT emailaddr_registrable_domain('someone@mail.corp.example.co.uk')"
+        ).expect("SPI result should not be NULL").unwrap();
+        assert_eq!(domain, "example.co.uk");
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let gmail = builtin_canonical_rule("googlemail.com");
+        let canonical = |s: &str, rule: Option<&CanonicalRule>| {
+            EmailAddr::from_str(s).unwrap().canonicalize(rule).to_string()
+        };
+
+        assert_eq!(canonical("J.O.H.N+promo@GoogleMail.com", gmail.as_ref()), "john@gmail.com");
+        assert_eq!(canonical("john@gmail.com", gmail.as_ref()), "john@gmail.com");
+        // Stripping must not leave an empty local part
+        assert_eq!(canonical("+promo@gmail.com", gmail.as_ref()), "+promo@gmail.com");
+
+        // Without a rule only the domain is lowercased
+        assert_eq!(canonical("J.Doe+tag@Example.COM", None), "J.Doe+tag@example.com");
+
+        let yahoo = CanonicalRule {
+            canonical_domain: None,
+            strip_dots: false,
+            strip_subaddress: true,
+            subaddress_separator: '-',
+            lowercase_local: false,
+        };
+        assert_eq!(canonical("j.doe-shop@yahoo.com", Some(&yahoo)), "j.doe@yahoo.com");
+    }
+
+    #[pg_test]
+    fn test_canonical_rules_table() {
+        let canonical = |s: &str| {
+            Spi::get_one_with_args::<String>(
+                "SELECT emailaddr_canonical($1::emailaddr)::text",
+                &[s.into()],
+            ).expect("SPI result should not be NULL").unwrap()
+        };
+
+        assert_eq!(canonical("J.O.H.N+promo@gmail.com"), "john@gmail.com");
+        assert_eq!(canonical("a.b-tag@example.org"), "a.b-tag@example.org");
+
+        Spi::run(
+            "INSERT INTO emailaddr_canonical_rules (domain, canonical_domain, strip_subaddress, subaddress_separator) \
+             VALUES ('example.org', 'example.com', true, '-')"
+        ).unwrap();
+        assert_eq!(canonical("a.b-tag@Example.org"), "a.b@example.com");
+
+        // A row overrides the built-in rule
+        Spi::run("INSERT INTO emailaddr_canonical_rules (domain) VALUES ('gmail.com')").unwrap();
+        assert_eq!(canonical("J.O.H.N+promo@gmail.com"), "J.O.H.N+promo@gmail.com");
+
+        // but not for emailaddr_canonical_builtin
+        let builtin = Spi::get_one::<String>(
+            "SELECT emailaddr_canonical_builtin('J.O.H.N+promo@gmail.com')::text"
+        ).expect("SPI result should not be NULL").unwrap();
+        assert_eq!(builtin, "john@gmail.com");
+    }
+
+    #[pg_test]
+    fn test_canonical_builtin_unique_index() {
+        Spi::run(
+            "CREATE TABLE canonical_emails (email emailaddr); \
+             CREATE UNIQUE INDEX canonical_emails_idx ON canonical_emails (emailaddr_canonical_builtin(email)); \
+             INSERT INTO canonical_emails VALUES ('john@gmail.com'); \
+             CREATE FUNCTION pg_temp.sqlstate_of(query text) RETURNS text LANGUAGE plpgsql AS $$ \
+             BEGIN EXECUTE query; RETURN NULL; \
+             EXCEPTION WHEN OTHERS THEN RETURN SQLSTATE; END $$"
+        ).unwrap();
+
+        // A second spelling of the same mailbox is rejected
+        let sqlstate = Spi::get_one::<String>(
+            "SELECT pg_temp.sqlstate_of('INSERT INTO canonical_emails VALUES (''J.O.H.N+x@googlemail.com'')')"
+        ).expect("SPI result should not be NULL").unwrap();
+        assert_eq!(sqlstate, "23505");
+    }
+
+    #[pg_test]
+    fn test_equivalence_operator() {
+        let equivalent = Spi::get_one::<bool>(
+            "SELECT 'j.o.h.n+promo@gmail.com'::emailaddr ~= 'john@googlemail.com'::emailaddr"
+        ).expect("SPI result should not be NULL").unwrap();
+        assert!(equivalent);
+
+        let equivalent = Spi::get_one::<bool>(
+            "SELECT 'j.o.h.n@example.com'::emailaddr ~= 'john@example.com'::emailaddr"
+        ).expect("SPI result should not be NULL").unwrap();
+        assert!(!equivalent);
+
+        // The operator follows changes to the rules
+        Spi::run(
+            "INSERT INTO emailaddr_canonical_rules (domain, strip_dots) VALUES ('example.com', true)"
+        ).unwrap();
+        let equivalent = Spi::get_one::<bool>(
+            "SELECT 'j.o.h.n@example.com'::emailaddr ~= 'john@example.com'::emailaddr"
+        ).expect("SPI result should not be NULL").unwrap();
+        assert!(equivalent);
+    }
+
+    #[pg_test]
+    fn test_domain_range() {
+        let (start, end) = EmailAddr::domain_range("Example.COM.").unwrap();
+        let in_range = |s: &str| {
+            let email = EmailAddr::from_str(s).unwrap();
+            start <= email && email < end
+        };
+
+        assert!(in_range("a@example.com"));
+        assert!(in_range("z@Mail.Example.com"));
+        assert!(in_range("a@b.c.example.com"));
+        assert!(!in_range("a@example.org"));
+        assert!(!in_range("a@notexample.com"));
+        assert!(!in_range("a@example-foo.com"));
+        assert!(!in_range("a@example.com.au"));
+        assert!(!in_range("a@mail.com"));
+
+        let (start, end) = EmailAddr::domain_range("bücher.de").unwrap();
+        let email = EmailAddr::from_str("user@mail.xn--bcher-kva.de").unwrap();
+        assert!(start <= email && email < end);
+
+        assert!(EmailAddr::domain_range("").is_err());
+    }
+
+    #[pg_test]
+    fn test_in_domain_operator() {
+        Spi::run(
+            "CREATE TABLE domain_emails (email emailaddr); \
+             CREATE INDEX domain_emails_idx ON domain_emails (email); \
+             INSERT INTO domain_emails SELECT format('user%s@host%s.com', i, i % 100)::emailaddr FROM generate_series(1, 1000) i; \
+             INSERT INTO domain_emails VALUES ('a@example.com'), ('b@Mail.Example.com'), ('c@example.org'), ('d@notexample.com'); \
+             ANALYZE domain_emails"
+        ).unwrap();
+
+        let matches = Spi::get_one::<String>(
+            "SELECT string_agg(email::text, ',' ORDER BY email) FROM domain_emails WHERE email <@ 'example.com'"
+        ).expect("SPI result should not be NULL").unwrap();
+        assert_eq!(matches, "a@example.com,b@Mail.Example.com");
+
+        let plan = Spi::get_one::<pgrx::JsonB>(
+            "EXPLAIN (FORMAT json) SELECT * FROM domain_emails WHERE email <@ 'example.com'"
+        ).expect("SPI result should not be NULL").unwrap();
+        assert!(plan.0.to_string().contains("domain_emails_idx"));
+
+        Spi::run(
+            "CREATE FUNCTION pg_temp.sqlstate_of(query text) RETURNS text LANGUAGE plpgsql AS $$ \
+             BEGIN EXECUTE query; RETURN NULL; \
+             EXCEPTION WHEN OTHERS THEN RETURN SQLSTATE; END $$"
+        ).unwrap();
+        let sqlstate = Spi::get_one::<String>(
+            "SELECT pg_temp.sqlstate_of($$SELECT 'a@example.com'::emailaddr <@ ''$$)"
+        ).expect("SPI result should not be NULL").unwrap();
+        assert_eq!(sqlstate, "22023");
+    }
+
+    #[pg_test]
+    fn test_idna_normalization() {
+        let unicode = EmailAddr::from_str("user@BÜCHER.de").unwrap();
+        assert_eq!(unicode.to_string(), "user@bücher.de");
+        assert_eq!(unicode.ascii_domain.as_deref(), Some("xn--bcher-kva.de"));
+
+        // A-label and U-label spellings are the same address
+        let ascii = EmailAddr::from_str("user@xn--bcher-KVA.de").unwrap();
+        assert_eq!(ascii.to_string(), "user@xn--bcher-KVA.de");
+        assert_eq!(unicode, ascii);
+        assert_eq!(unicode.cmp(&ascii), Ordering::Equal);
+
+        let hash = |e: &EmailAddr| {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            e.hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(&unicode), hash(&ascii));
+
+        assert_eq!(unicode.to_ascii().to_string(), "user@xn--bcher-kva.de");
+        assert_eq!(ascii.to_unicode().to_string(), "user@bücher.de");
+        let plain = EmailAddr::from_str("User@Example.com").unwrap();
+        assert_eq!(plain.to_ascii().to_string(), "User@Example.com");
+
+        // IDNA mapping composes the domain
+        let decomposed = EmailAddr::from_str("user@cafe\u{301}.fr").unwrap();
+        assert_eq!(decomposed.to_string(), "user@café.fr");
+
+        // Invalid punycode is rejected
+        assert!(EmailAddr::from_str("user@xn--zz.com").is_err());
+    }
+
+    #[pg_test]
+    fn test_idna_storage_roundtrip() {
+        let email = EmailAddr::from_str("test@उदाहरण.परीक्षा").unwrap();
+        let bytes = email.encode();
+        assert_eq!(bytes[0], FORMAT_V2);
+
+        let decoded = EmailAddr::decode(&bytes).unwrap();
+        assert_eq!(decoded.data, email.data);
+        assert_eq!(decoded.ascii_domain.as_deref(), Some("xn--p1b6ci4b4b3a.xn--11b5bs3a9aj6g"));
+
+        // Format 1 values with a non-ASCII domain still compare by A-label
+        let mut v1 = vec![FORMAT_V1, 4, 0];
+        v1.extend_from_slice("test@bücher.de".as_bytes());
+        let decoded = EmailAddr::decode(&v1).unwrap();
+        assert_eq!(decoded, EmailAddr::from_str("test@xn--bcher-kva.de").unwrap());
+    }
+
+    #[pg_test]
+    fn test_idna_functions() {
+        let equal = Spi::get_one::<bool>(
+            "SELECT 'user@bücher.de'::emailaddr = 'user@xn--bcher-kva.de'::emailaddr"
+        ).expect("SPI result should not be NULL").unwrap();
+        assert!(equal);
+
+        let ascii = Spi::get_one::<String>("SELECT emailaddr_to_ascii('user@bücher.de')::text")
+            .expect("SPI result should not be NULL").unwrap();
+        assert_eq!(ascii, "user@xn--bcher-kva.de");
+
+        let unicode = Spi::get_one::<String>("SELECT emailaddr_to_unicode('user@xn--bcher-kva.de')::text")
+            .expect("SPI result should not be NULL").unwrap();
+        assert_eq!(unicode, "user@bücher.de");
+    }
+
+    #[pg_test]
+    fn test_storage_roundtrip() {
+        let email = EmailAddr::from_str("test@Example.com").unwrap();
+
+        let bytes = email.encode();
+        assert_eq!(bytes[0], FORMAT_V1);
+        assert_eq!(&bytes[V1_HEADER_LEN..], email.data.as_bytes());
+
+        let decoded = EmailAddr::decode(&bytes).unwrap();
+        assert_eq!(decoded.data, email.data);
+        assert_eq!(decoded.at, email.at);
+    }
+
+    #[pg_test]
+    fn test_legacy_cbor_decoding() {
+        // {"data": "test@example.com"} as written by 0.0.1
+        let mut legacy = b"\xa1\x64data\x70".to_vec();
+        legacy.extend_from_slice(b"test@example.com");
+
+        let email = EmailAddr::decode(&legacy).unwrap();
+        assert_eq!(email.to_string(), "test@example.com");
+        assert_eq!(email.split(), ("test", "example.com"));
+
+        // Longer addresses use a one-byte length prefix
+        let address = format!("{}@example.com", "a".repeat(30));
+        let mut legacy = vec![0xa1, 0x64, b'd', b'a', b't', b'a', 0x78, address.len() as u8];
+        legacy.extend_from_slice(address.as_bytes());
+        assert_eq!(EmailAddr::decode(&legacy).unwrap().to_string(), address);
+
+        assert!(EmailAddr::decode(b"\xa1\x64data\x75short").is_err());
+        assert!(EmailAddr::decode(b"").is_err());
+    }
+
+    #[pg_test]
+    fn test_wire_format() {
+        let email = EmailAddr::from_str("test@Example.com").unwrap();
+        let bytes = email.to_wire();
+        assert_eq!(bytes[0], WIRE_FORMAT_V1);
+        assert_eq!(&bytes[1..], b"test@Example.com");
+        assert_eq!(EmailAddr::from_wire(&bytes).unwrap().to_string(), "test@Example.com");
+
+        // Binary input is validated like text input
+        assert!(EmailAddr::from_wire(b"\x01not-an-email").is_err());
+        assert!(EmailAddr::from_wire(b"\x02test@example.com").is_err());
+        assert!(EmailAddr::from_wire(b"").is_err());
+
+        // Values stored under another validation profile load back
+        let quoted = EmailAddr::from_wire(b"\x01\"john doe\"@example.com").unwrap();
+        assert_eq!(quoted.to_string(), r#""john doe"@example.com"#);
+
+        let sent = Spi::get_one::<Vec<u8>>("SELECT emailaddr_send('a@b.com')")
+            .expect("SPI result should not be NULL").unwrap();
+        assert_eq!(sent, b"\x01a@b.com");
+    }
+
+    #[cfg(any(feature = "pg16", feature = "pg17", feature = "pg18"))]
+    #[pg_test]
+    fn test_soft_input_errors() {
+        let valid = Spi::get_one::<bool>("SELECT pg_input_is_valid('user@example.com', 'emailaddr')")
+            .expect("SPI result should not be NULL").unwrap();
+        assert!(valid);
+
+        let valid = Spi::get_one::<bool>("SELECT pg_input_is_valid('not-an-email', 'emailaddr')")
+            .expect("SPI result should not be NULL").unwrap();
+        assert!(!valid);
+
+        let message = Spi::get_one::<String>(
+            "SELECT message FROM pg_input_error_info('not-an-email', 'emailaddr')"
+        ).expect("SPI result should not be NULL").unwrap();
+        assert_eq!(message, "invalid input syntax for type emailaddr: invalid email address format");
+    }
+
+    #[pg_test]
+    fn test_compact_storage() {
+        // 4-byte varlena header + 3-byte emailaddr header + 16 address bytes
+        let size = Spi::get_one::<i32>(
+            "SELECT pg_column_size('test@example.com'::emailaddr)"
+        ).expect("SPI result should not be NULL").unwrap();
+        assert_eq!(size, 23);
+    }
+
+    /// Addresses and whether the default profile accepts them
+    fn validate_email_cases() -> Vec<(&'static str, bool)> {
+        vec![
             ("email@here.com", true),
             ("weirder-email@here.and.there.com", true),
             (r#"!def!xyz%abc@example.com"#, true),
@@ -190,9 +1508,13 @@ mod tests {
             ("a@[127.0.0.1]\n", false),
             // underscores are not allowed
             ("John.Doe@exam_ple.com", false),
-        ];
+        ]
+    }
 
-        for (input, expected) in tests {
+        #[test]
+    fn test_validate_email() {
+
+        for (input, expected) in validate_email_cases() {
             // println!("{} - {}", input, expected);
             assert_eq!(
                 EmailAddr::from_str(input).is_ok(),
@@ -203,6 +1525,39 @@ mod tests {
         }
     }
 
+    #[pg_test]
+    fn test_emailaddr_validate() {
+        for (input, expected) in validate_email_cases() {
+            let valid = Spi::get_one_with_args::<bool>(
+                "SELECT valid FROM emailaddr_validate($1)",
+                &[input.into()],
+            ).expect("SPI result should not be NULL").unwrap();
+            assert_eq!(valid, expected, "Email `{}` was not classified correctly", input);
+        }
+
+        let reason = |input: &str| {
+            let row = Spi::get_three_with_args::<String, i32, String>(
+                "SELECT reason_code, \"position\", part FROM emailaddr_validate($1)",
+                &[input.into()],
+            ).unwrap();
+            (row.0.unwrap(), row.1, row.2.unwrap())
+        };
+
+        let long_local = format!("{}@example.com", "a".repeat(65));
+        assert_eq!(reason(&long_local), ("local_part_too_long".into(), Some(1), "local_part".into()));
+        let long_label = format!("a@{}.com", "b".repeat(64));
+        assert_eq!(reason(&long_label), ("label_too_long".into(), Some(3), "domain".into()));
+        assert_eq!(reason("John.Doe@exam_ple.com"), ("underscore_in_domain".into(), Some(14), "domain".into()));
+        assert_eq!(reason("trailingdot@shouldfail.com."), ("trailing_dot".into(), Some(27), "domain".into()));
+        assert_eq!(reason("email@[127.0.0.256]"), ("invalid_address_literal".into(), Some(7), "domain".into()));
+        assert_eq!(reason("abc"), ("missing_at".into(), None, "address".into()));
+
+        let (valid, reason_code) = Spi::get_two::<bool, String>(
+            "SELECT valid, reason_code FROM emailaddr_validate('user@example.com')"
+        ).unwrap();
+        assert_eq!((valid, reason_code), (Some(true), None));
+    }
+
     #[test]
     fn test_validate_email_rfc5321() {
         // 65 character local part
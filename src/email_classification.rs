@@ -0,0 +1,222 @@
+use pgrx::prelude::*;
+use std::collections::HashSet;
+use std::rc::Rc;
+use crate::config::{self, TableCache};
+use crate::email_addr::EmailAddr;
+
+/// Domains of free webmail providers
+const FREE_PROVIDERS: &[&str] = &[
+    "126.com", "163.com", "aol.com", "fastmail.com", "free.fr", "gmail.com", "gmx.com", "gmx.de",
+    "gmx.net", "googlemail.com", "hanmail.net", "hotmail.co.uk", "hotmail.com", "hotmail.fr",
+    "icloud.com", "interia.pl", "libero.it", "live.com", "mac.com", "mail.com", "mail.ru", "me.com",
+    "msn.com", "naver.com", "o2.pl", "orange.fr", "outlook.com", "pm.me", "proton.me",
+    "protonmail.com", "qq.com", "rediffmail.com", "rocketmail.com", "seznam.cz", "tutanota.com",
+    "web.de", "wp.pl", "yahoo.co.jp", "yahoo.co.uk", "yahoo.com", "yahoo.com.tw", "yahoo.fr",
+    "yandex.com", "yandex.ru", "ymail.com", "zoho.com",
+];
+
+/// Domains of disposable (burner) address services
+const DISPOSABLE: &[&str] = &[
+    "10minutemail.com", "33mail.com", "burnermail.io", "discard.email", "dispostable.com",
+    "emailondeck.com", "fakeinbox.com", "getnada.com", "grr.la", "guerrillamail.com",
+    "guerrillamail.net", "maildrop.cc", "mailinator.com", "mailnesia.com", "mintemail.com",
+    "mohmal.com", "mytemp.email", "sharklasers.com", "spam4.me", "spamgourmet.com", "temp-mail.io",
+    "temp-mail.org", "tempmail.com", "tempr.email", "throwawaymail.com", "trashmail.com",
+    "yopmail.com",
+];
+
+/// Local parts of role accounts, which belong to a function rather than a
+/// person
+const ROLE_ACCOUNTS: &[&str] = &[
+    "abuse", "admin", "administrator", "billing", "careers", "contact", "do-not-reply",
+    "donotreply", "enquiries", "help", "hostmaster", "hr", "info", "inquiries", "jobs",
+    "mailer-daemon", "marketing", "news", "newsletter", "no-reply", "noreply", "notifications",
+    "office", "postmaster", "press", "privacy", "root", "sales", "security", "support", "sysadmin",
+    "team", "webmaster",
+];
+
+/// The built-in lists plus the rows of `emailaddr_classification_entries`
+#[derive(Debug, Default)]
+struct ClassificationLists {
+    free_providers: HashSet<String>,
+    disposable: HashSet<String>,
+    role_accounts: HashSet<String>,
+}
+
+impl ClassificationLists {
+    fn builtin() -> Self {
+        let set = |list: &[&str]| list.iter().map(|s| s.to_string()).collect();
+        ClassificationLists {
+            free_providers: set(FREE_PROVIDERS),
+            disposable: set(DISPOSABLE),
+            role_accounts: set(ROLE_ACCOUNTS),
+        }
+    }
+
+    /// Whether `domain` or one of its parent domains is in `list`, so
+    /// subdomains of disposable services are caught too.
+    fn matches_domain(list: &HashSet<String>, domain: &str) -> bool {
+        std::iter::once(domain)
+            .chain(domain.match_indices('.').map(|(i, _)| &domain[i + 1..]))
+            .any(|suffix| list.contains(suffix))
+    }
+
+    fn classify(&self, email: &EmailAddr) -> Classification {
+        let domain = email.domain();
+        // `admin+alerts@` is still the admin role account
+        let mailbox = email.split_subaddress('+').0.to_lowercase();
+
+        Classification {
+            free_provider: Self::matches_domain(&self.free_providers, &domain),
+            disposable: Self::matches_domain(&self.disposable, &domain),
+            role_account: self.role_accounts.contains(&mailbox),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Classification {
+    free_provider: bool,
+    disposable: bool,
+    role_account: bool,
+}
+
+thread_local! {
+    static LISTS: TableCache<ClassificationLists> = const { std::cell::RefCell::new(None) };
+}
+
+/// The lists in effect, cached per backend until
+/// `emailaddr_classification_entries` changes.
+fn current_lists() -> Rc<ClassificationLists> {
+    config::cached_table(&LISTS, "emailaddr_classification_entries", |table| {
+        let mut lists = ClassificationLists::builtin();
+        Spi::connect(|client| {
+            let rows = client.select(&format!("SELECT kind, lower(value) FROM {}", table), None, &[])?;
+            for row in rows {
+                let (Some(kind), Some(value)) = (row.get::<String>(1)?, row.get::<String>(2)?) else {
+                    continue;
+                };
+                let list = match kind.as_str() {
+                    "free_provider" => &mut lists.free_providers,
+                    "disposable" => &mut lists.disposable,
+                    _ => &mut lists.role_accounts,
+                };
+                list.insert(value);
+            }
+            Ok::<_, pgrx::spi::Error>(())
+        })
+        .unwrap_or_else(|e| error!("could not read {}: {}", table, e));
+        lists
+    })
+}
+
+extension_sql!(
+    r#"
+CREATE TYPE emailaddr_classification AS (
+    free_provider boolean,
+    disposable boolean,
+    role_account boolean
+);
+
+-- Entries added to the built-in lists of emailaddr_classify(). value is a
+-- domain for free_provider and disposable, a local part for role_account.
+CREATE TABLE emailaddr_classification_entries (
+    kind text NOT NULL CHECK (kind IN ('free_provider', 'disposable', 'role_account')),
+    value text NOT NULL,
+    PRIMARY KEY (kind, value)
+);
+SELECT pg_catalog.pg_extension_config_dump('emailaddr_classification_entries', '');
+
+CREATE TRIGGER emailaddr_classification_entries_changed
+    AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON emailaddr_classification_entries
+    FOR EACH STATEMENT EXECUTE FUNCTION pgdatatypes_plus_config_changed();
+"#,
+    name = "emailaddr_classification",
+    requires = [crate::config::pgdatatypes_plus_config_changed],
+);
+
+/// Whether the address is at a free webmail provider, at a disposable
+/// address service, or a role account such as `admin@` or `noreply@`
+#[pg_extern(stable, parallel_safe, requires = ["emailaddr_classification"])]
+fn emailaddr_classify(input: EmailAddr) -> pgrx::composite_type!('static, "emailaddr_classification") {
+    let classification = current_lists().classify(&input);
+
+    let mut result = PgHeapTuple::new_composite_type("emailaddr_classification").unwrap();
+    result.set_by_name("free_provider", classification.free_provider).unwrap();
+    result.set_by_name("disposable", classification.disposable).unwrap();
+    result.set_by_name("role_account", classification.role_account).unwrap();
+    result
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[pg_test]
+    fn test_builtin_lists() {
+        let lists = ClassificationLists::builtin();
+        let classify = |s: &str| lists.classify(&EmailAddr::from_str(s).unwrap());
+
+        assert_eq!(
+            classify("jane@Gmail.com"),
+            Classification { free_provider: true, disposable: false, role_account: false }
+        );
+        assert_eq!(
+            classify("x@eu.mailinator.com"),
+            Classification { free_provider: false, disposable: true, role_account: false }
+        );
+        assert_eq!(
+            classify("NoReply+alerts@example.com"),
+            Classification { free_provider: false, disposable: false, role_account: true }
+        );
+        assert_eq!(
+            classify("jane@example.com"),
+            Classification { free_provider: false, disposable: false, role_account: false }
+        );
+        // Only whole labels match
+        assert!(!classify("jane@notgmail.com").free_provider);
+    }
+
+    #[pg_test]
+    fn test_config_table() {
+        let flags = |s: &str| {
+            Spi::get_three_with_args::<bool, bool, bool>(
+                "SELECT (c).free_provider, (c).disposable, (c).role_account \
+                 FROM (SELECT emailaddr_classify($1::emailaddr) AS c) t",
+                &[s.into()],
+            )
+            .unwrap()
+        };
+
+        assert_eq!(flags("ops@burner.example"), (Some(false), Some(false), Some(false)));
+
+        Spi::run(
+            "INSERT INTO emailaddr_classification_entries VALUES \
+             ('disposable', 'burner.example'), ('role_account', 'OPS')"
+        ).unwrap();
+        assert_eq!(flags("ops@burner.example"), (Some(false), Some(true), Some(true)));
+
+        Spi::run("DELETE FROM emailaddr_classification_entries WHERE kind = 'disposable'").unwrap();
+        assert_eq!(flags("ops@burner.example"), (Some(false), Some(false), Some(true)));
+    }
+
+    #[pg_test]
+    fn test_check_constraint() {
+        Spi::run(
+            "CREATE TABLE signups (email emailaddr CHECK (NOT (emailaddr_classify(email)).disposable)); \
+             INSERT INTO signups VALUES ('jane@example.com')"
+        ).unwrap();
+
+        Spi::run(
+            "CREATE FUNCTION pg_temp.sqlstate_of(query text) RETURNS text LANGUAGE plpgsql AS $$ \
+             BEGIN EXECUTE query; RETURN NULL; \
+             EXCEPTION WHEN OTHERS THEN RETURN SQLSTATE; END $$"
+        ).unwrap();
+        let sqlstate = Spi::get_one::<String>(
+            "SELECT pg_temp.sqlstate_of('INSERT INTO signups VALUES (''x@yopmail.com'')')"
+        ).expect("SPI result should not be NULL").unwrap();
+        assert_eq!(sqlstate, "23514");
+    }
+}
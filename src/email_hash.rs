@@ -0,0 +1,266 @@
+use pgrx::prelude::*;
+use pgrx::pg_sys::panic::ErrorReport;
+use pgrx::{GucContext, GucFlags, GucRegistry, GucSetting};
+use hmac::digest::KeyInit;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256, Sha512};
+use std::ffi::CString;
+use std::str::FromStr;
+use crate::email_addr::{builtin_canonical_rule, EmailAddr};
+use crate::error::{raise, InvalidValue};
+
+/// Key for the HMAC algorithms. Only superusers can set or show it.
+static HASH_KEY: GucSetting<Option<CString>> = GucSetting::<Option<CString>>::new(None);
+
+pub(crate) fn init() {
+    GucRegistry::define_string_guc(
+        c"pgdatatypes_plus.hash_key",
+        c"Secret key for the HMAC algorithms of emailaddr_hashed_id.",
+        c"Only superusers can set or show the key. Set it per database or role so that other users can compute keyed hashes without seeing it.",
+        &HASH_KEY,
+        GucContext::Suset,
+        GucFlags::SUPERUSER_ONLY | GucFlags::NO_SHOW_ALL,
+    );
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HashAlgorithm {
+    Md5,
+    Sha1,
+    Sha256,
+    Sha512,
+    HmacSha256,
+    HmacSha512,
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = InvalidValue;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "md5" => Ok(HashAlgorithm::Md5),
+            "sha1" => Ok(HashAlgorithm::Sha1),
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            "sha512" => Ok(HashAlgorithm::Sha512),
+            "hmac-sha256" => Ok(HashAlgorithm::HmacSha256),
+            "hmac-sha512" => Ok(HashAlgorithm::HmacSha512),
+            _ => Err(InvalidValue::new("invalid hash algorithm")
+                .with_detail(format!("Unknown algorithm \"{}\".", s))
+                .with_hint("Use md5, sha1, sha256, sha512, hmac-sha256 or hmac-sha512.")),
+        }
+    }
+}
+
+impl HashAlgorithm {
+    fn hash(self, data: &[u8], key: impl FnOnce() -> Vec<u8>) -> Vec<u8> {
+        fn keyed<M: Mac + KeyInit>(key: &[u8], data: &[u8]) -> Vec<u8> {
+            // HMAC takes keys of any length
+            let mut mac = <M as Mac>::new_from_slice(key).unwrap();
+            mac.update(data);
+            mac.finalize().into_bytes().to_vec()
+        }
+
+        match self {
+            HashAlgorithm::Md5 => md5::Md5::digest(data).to_vec(),
+            HashAlgorithm::Sha1 => sha1::Sha1::digest(data).to_vec(),
+            HashAlgorithm::Sha256 => Sha256::digest(data).to_vec(),
+            HashAlgorithm::Sha512 => Sha512::digest(data).to_vec(),
+            HashAlgorithm::HmacSha256 => keyed::<Hmac<Sha256>>(&key(), data),
+            HashAlgorithm::HmacSha512 => keyed::<Hmac<Sha512>>(&key(), data),
+        }
+    }
+}
+
+/// How the address is spelled before hashing, so that every spelling of
+/// one mailbox hashes the same.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Normalization {
+    /// The address as stored
+    None,
+    /// The whole address in lowercase, with the domain in A-label form
+    Standard,
+    /// `standard`, and Gmail addresses without dots and `+tag`
+    Gmail,
+    /// `standard` applied to `emailaddr_canonical()` of the address
+    Canonical,
+}
+
+impl FromStr for Normalization {
+    type Err = InvalidValue;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Normalization::None),
+            "standard" => Ok(Normalization::Standard),
+            "gmail" => Ok(Normalization::Gmail),
+            "canonical" => Ok(Normalization::Canonical),
+            _ => Err(InvalidValue::new("invalid normalization")
+                .with_detail(format!("Unknown normalization \"{}\".", s))
+                .with_hint("Use none, standard, gmail or canonical.")),
+        }
+    }
+}
+
+/// The lowercase local part and the lowercase A-label domain, so that the
+/// Unicode and ASCII spellings of a domain hash the same, as they compare
+/// equal.
+fn standard(email: &EmailAddr) -> String {
+    format!("{}@{}", email.local().to_lowercase(), email.domain_key().to_ascii_lowercase())
+}
+
+impl Normalization {
+    fn apply(self, email: &EmailAddr) -> String {
+        match self {
+            Normalization::None => email.to_string(),
+            Normalization::Standard => standard(email),
+            Normalization::Gmail => {
+                let rule = builtin_canonical_rule(&email.domain());
+                standard(&email.canonicalize(rule.as_ref()))
+            }
+            Normalization::Canonical => standard(&email.canonical()),
+        }
+    }
+}
+
+/// The key in `pgdatatypes_plus.hash_key`, which must be set.
+fn hash_key() -> Vec<u8> {
+    match HASH_KEY.get() {
+        Some(key) if !key.as_bytes().is_empty() => key.into_bytes(),
+        _ => raise(
+            ErrorReport::new(
+                PgSqlErrorCode::ERRCODE_OBJECT_NOT_IN_PREREQUISITE_STATE,
+                "pgdatatypes_plus.hash_key is not set",
+                pgrx::function_name!(),
+            )
+            .set_hint("A superuser must set pgdatatypes_plus.hash_key to use the HMAC algorithms."),
+        ),
+    }
+}
+
+fn hashed_id(email: &EmailAddr, algorithm: &str, normalization: &str) -> Result<Vec<u8>, ErrorReport> {
+    let algorithm = HashAlgorithm::from_str(algorithm).map_err(InvalidValue::parameter_report)?;
+    let normalization = Normalization::from_str(normalization).map_err(InvalidValue::parameter_report)?;
+
+    Ok(algorithm.hash(normalization.apply(email).as_bytes(), hash_key))
+}
+
+/// A hash of the normalised address, for matching audiences with partners
+/// without sharing the address itself: `sha256` of the lowercased address
+/// by default. Use `encode(..., 'hex')` for the hex form.
+#[pg_extern(stable, parallel_safe)]
+fn emailaddr_hashed_id(
+    input: EmailAddr,
+    algorithm: default!(&str, "'sha256'"),
+    normalization: default!(&str, "'standard'"),
+) -> Result<Vec<u8>, ErrorReport> {
+    hashed_id(&input, algorithm, normalization)
+}
+
+/// Like `emailaddr_hashed_id(emailaddr, ...)`, for raw input: surrounding
+/// whitespace is trimmed and the rest parsed as an `emailaddr`.
+#[pg_extern(stable, parallel_safe, name = "emailaddr_hashed_id")]
+fn emailaddr_hashed_id_text(
+    input: &str,
+    algorithm: default!(&str, "'sha256'"),
+    normalization: default!(&str, "'standard'"),
+) -> Result<Vec<u8>, ErrorReport> {
+    let email = EmailAddr::from_str(input.trim()).map_err(|e| e.input_report("emailaddr"))?;
+    hashed_id(&email, algorithm, normalization)
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_hash_algorithms() {
+        let no_key = || -> Vec<u8> { unreachable!() };
+        let key = || b"key".to_vec();
+
+        assert_eq!(hex(&HashAlgorithm::Md5.hash(b"", no_key)), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(
+            hex(&HashAlgorithm::Sha256.hash(b"abc", no_key)),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex(&HashAlgorithm::HmacSha256.hash(b"The quick brown fox jumps over the lazy dog", key)),
+            "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+
+        assert_eq!(HashAlgorithm::from_str("SHA256"), Ok(HashAlgorithm::Sha256));
+        assert!(HashAlgorithm::from_str("crc32").is_err());
+        assert!(Normalization::from_str("trim").is_err());
+    }
+
+    #[pg_test]
+    fn test_normalizations() {
+        let normalize = |s: &str, normalization: Normalization| {
+            normalization.apply(&EmailAddr::from_str(s).unwrap())
+        };
+
+        let gmail = "J.Doe+promo@GoogleMail.com";
+        assert_eq!(normalize(gmail, Normalization::None), gmail);
+        assert_eq!(normalize(gmail, Normalization::Standard), "j.doe+promo@googlemail.com");
+        assert_eq!(normalize(gmail, Normalization::Gmail), "jdoe@gmail.com");
+        assert_eq!(normalize(gmail, Normalization::Canonical), "jdoe@gmail.com");
+
+        // Only Gmail addresses lose their dots and tags
+        assert_eq!(normalize("J.Doe+x@Example.com", Normalization::Gmail), "j.doe+x@example.com");
+        assert_eq!(normalize("Jane@Bücher.de", Normalization::Standard), "jane@xn--bcher-kva.de");
+    }
+
+    #[pg_test]
+    fn test_idn_spellings_hash_the_same() {
+        let id = |s: &str| hashed_id(&EmailAddr::from_str(s).unwrap(), "sha256", "standard").unwrap();
+        assert_eq!(id("user@bücher.de"), id("user@xn--bcher-kva.de"));
+        assert_eq!(id("user@BÜCHER.de"), id("User@XN--BCHER-KVA.DE"));
+    }
+
+    #[pg_test]
+    fn test_emailaddr_hashed_id() {
+        let hashed_id = |query: &str| Spi::get_one::<String>(query).unwrap();
+
+        let sha256 = hashed_id("SELECT encode(sha256('jane.doe@example.com'), 'hex')");
+        assert_eq!(
+            hashed_id("SELECT encode(emailaddr_hashed_id('Jane.Doe@Example.COM'::emailaddr), 'hex')"),
+            sha256
+        );
+        assert_eq!(
+            hashed_id("SELECT encode(emailaddr_hashed_id('  Jane.Doe@Example.COM '), 'hex')"),
+            sha256
+        );
+
+        assert_eq!(
+            hashed_id("SELECT encode(emailaddr_hashed_id('Jane@Example.com', 'md5', 'none'), 'hex')"),
+            hashed_id("SELECT md5('Jane@Example.com')")
+        );
+    }
+
+    #[pg_test]
+    fn test_emailaddr_hashed_id_hmac() {
+        Spi::run(
+            "CREATE FUNCTION pg_temp.sqlstate_of(query text) RETURNS text LANGUAGE plpgsql AS $$ \
+             BEGIN EXECUTE query; RETURN NULL; \
+             EXCEPTION WHEN OTHERS THEN RETURN SQLSTATE; END $$"
+        ).unwrap();
+        let sqlstate_of = |query: &str| {
+            Spi::get_one_with_args::<String>("SELECT pg_temp.sqlstate_of($1)", &[query.into()]).unwrap()
+        };
+
+        let hmac = "SELECT emailaddr_hashed_id('jane@example.com', 'hmac-sha256')";
+        assert_eq!(sqlstate_of(hmac).as_deref(), Some("55000"));
+
+        Spi::run("SET pgdatatypes_plus.hash_key = 'secret'").unwrap();
+        let expected = HashAlgorithm::HmacSha256.hash(b"jane@example.com", || b"secret".to_vec());
+        let actual = Spi::get_one::<Vec<u8>>(hmac).unwrap();
+        assert_eq!(actual, Some(expected));
+
+        assert_eq!(sqlstate_of("SELECT emailaddr_hashed_id('jane@example.com', 'crc32')").as_deref(), Some("22023"));
+        assert_eq!(sqlstate_of("SELECT emailaddr_hashed_id('not an address')").as_deref(), Some("22P02"));
+    }
+}
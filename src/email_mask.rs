@@ -0,0 +1,154 @@
+use pgrx::prelude::*;
+use pgrx::pg_sys::panic::ErrorReport;
+use std::str::FromStr;
+use crate::email_addr::EmailAddr;
+use crate::error::InvalidValue;
+
+/// What replaces the local part, and whole domain labels in the `fixed`
+/// style. Always the same width, so masked addresses don't give away how
+/// long the local part is.
+const MASK: &str = "***";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MaskStyle {
+    /// `j***@e******.com`
+    FirstChar,
+    /// `***@example.com`
+    Domain,
+    /// `***@*******.com`
+    Tld,
+    /// `***@***.***`
+    Fixed,
+}
+
+impl FromStr for MaskStyle {
+    type Err = InvalidValue;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "first_char" => Ok(MaskStyle::FirstChar),
+            "domain" => Ok(MaskStyle::Domain),
+            "tld" => Ok(MaskStyle::Tld),
+            "fixed" => Ok(MaskStyle::Fixed),
+            _ => Err(InvalidValue::new("invalid mask style")
+                .with_detail(format!("Unknown style \"{}\".", s))
+                .with_hint("Use first_char, domain, tld or fixed.")),
+        }
+    }
+}
+
+/// `label` with every character but the first (if kept) replaced by `*`.
+fn mask_label(label: &str, keep_first: bool) -> String {
+    label
+        .chars()
+        .enumerate()
+        .map(|(i, c)| if i == 0 && keep_first { c } else { '*' })
+        .collect()
+}
+
+impl MaskStyle {
+    /// Masks `email`, keeping the `@` and the dots between domain labels.
+    fn mask(self, email: &EmailAddr) -> String {
+        let domain = email.domain();
+
+        let local = match self {
+            MaskStyle::FirstChar => {
+                // Show the first character inside a quoted local part, not the quote
+                let first = email.local().chars().find(|&c| c != '"' && c != '\\');
+                format!("{}{}", first.map(String::from).unwrap_or_default(), MASK)
+            }
+            _ => MASK.to_string(),
+        };
+
+        let domain = if self == MaskStyle::Domain {
+            domain
+        } else if domain.starts_with('[') {
+            // Address literals have no labels worth keeping
+            format!("[{}]", MASK)
+        } else {
+            // IP addresses have no TLD, so every label is masked
+            let has_tld = email.tld().is_some();
+            let labels: Vec<&str> = domain.split('.').collect();
+            labels
+                .iter()
+                .enumerate()
+                .map(|(i, label)| match self {
+                    MaskStyle::Fixed => MASK.to_string(),
+                    _ if has_tld && i == labels.len() - 1 => label.to_string(),
+                    MaskStyle::FirstChar => mask_label(label, has_tld),
+                    _ => mask_label(label, false),
+                })
+                .collect::<Vec<_>>()
+                .join(".")
+        };
+
+        format!("{}@{}", local, domain)
+    }
+}
+
+/// The address masked for display, in one of these styles:
+///
+/// - `first_char`: `j***@e******.com`
+/// - `domain`: `***@example.com`
+/// - `tld`: `***@*******.com`
+/// - `fixed`: `***@***.***`
+///
+/// The local part is always replaced by a fixed-width mask.
+#[pg_extern(immutable, parallel_safe)]
+fn emailaddr_mask(input: EmailAddr, style: default!(&str, "'first_char'")) -> Result<String, ErrorReport> {
+    let style = MaskStyle::from_str(style).map_err(InvalidValue::parameter_report)?;
+    Ok(style.mask(&input))
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+
+    fn email(s: &str) -> EmailAddr {
+        EmailAddr::from_validated(s.to_string(), s.rfind('@').unwrap())
+    }
+
+    #[test]
+    fn test_mask_styles() {
+        let mask = |s: &str, style: MaskStyle| style.mask(&email(s));
+
+        assert_eq!(mask("john.doe@Example.com", MaskStyle::FirstChar), "j***@e******.com");
+        assert_eq!(mask("john.doe@example.com", MaskStyle::Domain), "***@example.com");
+        assert_eq!(mask("john.doe@example.com", MaskStyle::Tld), "***@*******.com");
+        assert_eq!(mask("john.doe@example.com", MaskStyle::Fixed), "***@***.***");
+        assert_eq!(mask("jane@mail.example.co.uk", MaskStyle::FirstChar), "j***@m***.e******.c*.uk");
+        assert_eq!(mask("jane@mail.example.co.uk", MaskStyle::Fixed), "***@***.***.***.***");
+
+        // The local part's length never shows
+        assert_eq!(mask("j@example.com", MaskStyle::FirstChar), "j***@e******.com");
+        assert_eq!(mask("\"john doe\"@example.com", MaskStyle::FirstChar), "j***@e******.com");
+
+        assert_eq!(mask("jane@bücher.de", MaskStyle::FirstChar), "j***@b*****.de");
+        assert_eq!(mask("jane@[127.0.0.1]", MaskStyle::Tld), "***@[***]");
+        assert_eq!(mask("jane@127.0.0.1", MaskStyle::FirstChar), "j***@***.*.*.*");
+    }
+
+    #[test]
+    fn test_mask_style_names() {
+        assert_eq!(MaskStyle::from_str("first_char"), Ok(MaskStyle::FirstChar));
+        assert_eq!(MaskStyle::from_str("TLD"), Ok(MaskStyle::Tld));
+        assert!(MaskStyle::from_str("stars").is_err());
+    }
+
+    #[pg_test]
+    fn test_emailaddr_mask() {
+        let masked = Spi::get_one::<String>("SELECT emailaddr_mask('john.doe@example.com')").unwrap();
+        assert_eq!(masked.as_deref(), Some("j***@e******.com"));
+
+        let masked = Spi::get_one::<String>("SELECT emailaddr_mask('john.doe@example.com', 'domain')").unwrap();
+        assert_eq!(masked.as_deref(), Some("***@example.com"));
+    }
+
+    #[pg_test]
+    #[should_panic(expected = "invalid mask style")]
+    fn test_emailaddr_mask_invalid_style() {
+        Spi::get_one::<String>("SELECT emailaddr_mask('john.doe@example.com', 'stars')")
+            .expect("SPI call failed");
+    }
+}
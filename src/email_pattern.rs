@@ -0,0 +1,356 @@
+use pgrx::prelude::*;
+use std::cmp::Ordering;
+use crate::email_addr::EmailAddr;
+
+/// Orders addresses byte by byte like `text_pattern_ops` orders text, but
+/// consistent with `=`: the domain is compared in lowercase A-label form.
+fn pattern_cmp(a: &EmailAddr, b: &EmailAddr) -> Ordering {
+    a.pattern_bytes().cmp(b.pattern_bytes())
+}
+
+/// Compares an address with a range bound made by `emailaddr_like_prefix`
+/// or `emailaddr_regex_prefix`.
+fn text_pattern_cmp(a: &EmailAddr, b: &str) -> Ordering {
+    a.pattern_bytes().cmp(b.bytes())
+}
+
+/// The part of a literal prefix the index range can use: up to and including
+/// the first `@`. What follows it is the domain, which the pattern spells
+/// as entered but the index holds in lowercase A-label form.
+fn index_prefix(mut prefix: String) -> String {
+    if let Some(at) = prefix.find('@') {
+        prefix.truncate(at + 1);
+    }
+    prefix
+}
+
+/// The literal text a LIKE pattern starts with, using the default escape
+/// character `\`.
+fn like_prefix(pattern: &str) -> String {
+    let mut prefix = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '%' | '_' => break,
+            '\\' => match chars.next() {
+                Some(c) => prefix.push(c),
+                None => break,
+            },
+            c => prefix.push(c),
+        }
+    }
+    prefix
+}
+
+/// The literal text every match of a regular expression starts with, if
+/// it is anchored with `^`. Errs on the short side: anything it doesn't
+/// understand ends the prefix.
+fn regex_prefix(pattern: &str) -> String {
+    let Some(rest) = pattern.strip_prefix('^') else {
+        return String::new();
+    };
+    // An alternative may start a match with anything
+    if rest.contains('|') {
+        return String::new();
+    }
+
+    let mut prefix = String::new();
+    let mut chars = rest.chars().peekable();
+    while let Some(c) = chars.next() {
+        let literal = match c {
+            // `\.` is a literal dot, `\d` and the like are classes
+            '\\' => match chars.next() {
+                Some(c) if !c.is_ascii_alphanumeric() => c,
+                _ => break,
+            },
+            '.' | '[' | ']' | '(' | ')' | '{' | '}' | '*' | '+' | '?' | '^' | '$' => break,
+            c => c,
+        };
+        match chars.peek() {
+            // The character may not be there at all
+            Some('*' | '?' | '{') => break,
+            // The character is there, but may repeat
+            Some('+') => {
+                prefix.push(literal);
+                break;
+            }
+            _ => prefix.push(literal),
+        }
+    }
+    prefix
+}
+
+#[pg_extern(immutable, parallel_safe)]
+fn emailaddr_pattern_lt(a: EmailAddr, b: EmailAddr) -> bool {
+    pattern_cmp(&a, &b) == Ordering::Less
+}
+
+#[pg_extern(immutable, parallel_safe)]
+fn emailaddr_pattern_le(a: EmailAddr, b: EmailAddr) -> bool {
+    pattern_cmp(&a, &b) != Ordering::Greater
+}
+
+#[pg_extern(immutable, parallel_safe)]
+fn emailaddr_pattern_ge(a: EmailAddr, b: EmailAddr) -> bool {
+    pattern_cmp(&a, &b) != Ordering::Less
+}
+
+#[pg_extern(immutable, parallel_safe)]
+fn emailaddr_pattern_gt(a: EmailAddr, b: EmailAddr) -> bool {
+    pattern_cmp(&a, &b) == Ordering::Greater
+}
+
+#[pg_extern(immutable, parallel_safe)]
+fn emailaddr_pattern_cmp(a: EmailAddr, b: EmailAddr) -> i32 {
+    pattern_cmp(&a, &b) as i32
+}
+
+#[pg_extern(immutable, parallel_safe)]
+fn emailaddr_text_pattern_lt(a: EmailAddr, b: &str) -> bool {
+    text_pattern_cmp(&a, b) == Ordering::Less
+}
+
+#[pg_extern(immutable, parallel_safe)]
+fn emailaddr_text_pattern_le(a: EmailAddr, b: &str) -> bool {
+    text_pattern_cmp(&a, b) != Ordering::Greater
+}
+
+#[pg_extern(immutable, parallel_safe)]
+fn emailaddr_text_pattern_ge(a: EmailAddr, b: &str) -> bool {
+    text_pattern_cmp(&a, b) != Ordering::Less
+}
+
+#[pg_extern(immutable, parallel_safe)]
+fn emailaddr_text_pattern_gt(a: EmailAddr, b: &str) -> bool {
+    text_pattern_cmp(&a, b) == Ordering::Greater
+}
+
+#[pg_extern(immutable, parallel_safe)]
+fn emailaddr_text_pattern_cmp(a: EmailAddr, b: &str) -> i32 {
+    text_pattern_cmp(&a, b) as i32
+}
+
+/// The start of the index range a LIKE pattern can match in
+#[pg_extern(immutable, parallel_safe)]
+fn emailaddr_like_prefix(pattern: &str) -> String {
+    index_prefix(like_prefix(pattern))
+}
+
+/// The start of the index range a regular expression can match in
+#[pg_extern(immutable, parallel_safe)]
+fn emailaddr_regex_prefix(pattern: &str) -> String {
+    index_prefix(regex_prefix(pattern))
+}
+
+/// The end of the index range of `prefix`: past everything starting with it
+#[pg_extern(immutable, parallel_safe)]
+fn emailaddr_prefix_end(prefix: &str) -> String {
+    format!("{}{}", prefix, char::MAX)
+}
+
+extension_sql!(
+    r#"
+-- Byte-wise ordering for pattern matching, like text_pattern_ops. The
+-- (emailaddr, text) operators compare with the range bounds LIKE and ~
+-- derive from their pattern.
+CREATE OPERATOR ~<~ (
+    LEFTARG = emailaddr, RIGHTARG = emailaddr, FUNCTION = emailaddr_pattern_lt,
+    COMMUTATOR = ~>~, NEGATOR = ~>=~, RESTRICT = scalarltsel, JOIN = scalarltjoinsel
+);
+CREATE OPERATOR ~<=~ (
+    LEFTARG = emailaddr, RIGHTARG = emailaddr, FUNCTION = emailaddr_pattern_le,
+    COMMUTATOR = ~>=~, NEGATOR = ~>~, RESTRICT = scalarlesel, JOIN = scalarlejoinsel
+);
+CREATE OPERATOR ~>=~ (
+    LEFTARG = emailaddr, RIGHTARG = emailaddr, FUNCTION = emailaddr_pattern_ge,
+    COMMUTATOR = ~<=~, NEGATOR = ~<~, RESTRICT = scalargesel, JOIN = scalargejoinsel
+);
+CREATE OPERATOR ~>~ (
+    LEFTARG = emailaddr, RIGHTARG = emailaddr, FUNCTION = emailaddr_pattern_gt,
+    COMMUTATOR = ~<~, NEGATOR = ~<=~, RESTRICT = scalargtsel, JOIN = scalargtjoinsel
+);
+
+CREATE OPERATOR ~<~ (
+    LEFTARG = emailaddr, RIGHTARG = text, FUNCTION = emailaddr_text_pattern_lt,
+    NEGATOR = ~>=~, RESTRICT = scalarltsel, JOIN = scalarltjoinsel
+);
+CREATE OPERATOR ~<=~ (
+    LEFTARG = emailaddr, RIGHTARG = text, FUNCTION = emailaddr_text_pattern_le,
+    NEGATOR = ~>~, RESTRICT = scalarlesel, JOIN = scalarlejoinsel
+);
+CREATE OPERATOR ~>=~ (
+    LEFTARG = emailaddr, RIGHTARG = text, FUNCTION = emailaddr_text_pattern_ge,
+    NEGATOR = ~<~, RESTRICT = scalargesel, JOIN = scalargejoinsel
+);
+CREATE OPERATOR ~>~ (
+    LEFTARG = emailaddr, RIGHTARG = text, FUNCTION = emailaddr_text_pattern_gt,
+    NEGATOR = ~<=~, RESTRICT = scalargtsel, JOIN = scalargtjoinsel
+);
+
+CREATE OPERATOR FAMILY emailaddr_pattern_ops USING btree;
+CREATE OPERATOR CLASS emailaddr_pattern_ops FOR TYPE emailaddr USING btree FAMILY emailaddr_pattern_ops AS
+    OPERATOR 1 ~<~ (emailaddr, emailaddr),
+    OPERATOR 2 ~<=~ (emailaddr, emailaddr),
+    OPERATOR 3 = (emailaddr, emailaddr),
+    OPERATOR 4 ~>=~ (emailaddr, emailaddr),
+    OPERATOR 5 ~>~ (emailaddr, emailaddr),
+    FUNCTION 1 emailaddr_pattern_cmp(emailaddr, emailaddr);
+ALTER OPERATOR FAMILY emailaddr_pattern_ops USING btree ADD
+    OPERATOR 1 ~<~ (emailaddr, text),
+    OPERATOR 2 ~<=~ (emailaddr, text),
+    OPERATOR 4 ~>=~ (emailaddr, text),
+    OPERATOR 5 ~>~ (emailaddr, text),
+    FUNCTION 1 emailaddr_text_pattern_cmp(emailaddr, text);
+
+-- LIKE, ILIKE and regular expressions on the address as text. LIKE and ~
+-- are SQL functions the planner inlines, so the range of their literal
+-- prefix can be looked up in an emailaddr_pattern_ops index. Not STRICT,
+-- which would keep them from being inlined.
+CREATE FUNCTION emailaddr_like(emailaddr, text) RETURNS boolean
+    IMMUTABLE PARALLEL SAFE
+    LANGUAGE sql
+    AS 'SELECT $1 OPERATOR(@extschema@.~>=~) @extschema@.emailaddr_like_prefix($2)
+           AND $1 OPERATOR(@extschema@.~<~) @extschema@.emailaddr_prefix_end(@extschema@.emailaddr_like_prefix($2))
+           AND $1::text OPERATOR(pg_catalog.~~) $2';
+
+CREATE FUNCTION emailaddr_not_like(emailaddr, text) RETURNS boolean
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE sql
+    AS 'SELECT $1::text OPERATOR(pg_catalog.!~~) $2';
+
+CREATE FUNCTION emailaddr_ilike(emailaddr, text) RETURNS boolean
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE sql
+    AS 'SELECT $1::text OPERATOR(pg_catalog.~~*) $2';
+
+CREATE FUNCTION emailaddr_not_ilike(emailaddr, text) RETURNS boolean
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE sql
+    AS 'SELECT $1::text OPERATOR(pg_catalog.!~~*) $2';
+
+CREATE FUNCTION emailaddr_regex_match(emailaddr, text) RETURNS boolean
+    IMMUTABLE PARALLEL SAFE
+    LANGUAGE sql
+    AS 'SELECT $1 OPERATOR(@extschema@.~>=~) @extschema@.emailaddr_regex_prefix($2)
+           AND $1 OPERATOR(@extschema@.~<~) @extschema@.emailaddr_prefix_end(@extschema@.emailaddr_regex_prefix($2))
+           AND $1::text OPERATOR(pg_catalog.~) $2';
+
+CREATE FUNCTION emailaddr_not_regex_match(emailaddr, text) RETURNS boolean
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE sql
+    AS 'SELECT $1::text OPERATOR(pg_catalog.!~) $2';
+
+CREATE FUNCTION emailaddr_iregex_match(emailaddr, text) RETURNS boolean
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE sql
+    AS 'SELECT $1::text OPERATOR(pg_catalog.~*) $2';
+
+CREATE FUNCTION emailaddr_not_iregex_match(emailaddr, text) RETURNS boolean
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE sql
+    AS 'SELECT $1::text OPERATOR(pg_catalog.!~*) $2';
+
+CREATE OPERATOR ~~ (LEFTARG = emailaddr, RIGHTARG = text, FUNCTION = emailaddr_like, NEGATOR = !~~);
+CREATE OPERATOR !~~ (LEFTARG = emailaddr, RIGHTARG = text, FUNCTION = emailaddr_not_like, NEGATOR = ~~);
+CREATE OPERATOR ~~* (LEFTARG = emailaddr, RIGHTARG = text, FUNCTION = emailaddr_ilike, NEGATOR = !~~*);
+CREATE OPERATOR !~~* (LEFTARG = emailaddr, RIGHTARG = text, FUNCTION = emailaddr_not_ilike, NEGATOR = ~~*);
+CREATE OPERATOR ~ (LEFTARG = emailaddr, RIGHTARG = text, FUNCTION = emailaddr_regex_match, NEGATOR = !~);
+CREATE OPERATOR !~ (LEFTARG = emailaddr, RIGHTARG = text, FUNCTION = emailaddr_not_regex_match, NEGATOR = ~);
+CREATE OPERATOR ~* (LEFTARG = emailaddr, RIGHTARG = text, FUNCTION = emailaddr_iregex_match, NEGATOR = !~*);
+CREATE OPERATOR !~* (LEFTARG = emailaddr, RIGHTARG = text, FUNCTION = emailaddr_not_iregex_match, NEGATOR = ~*);
+"#,
+    name = "emailaddr_pattern_ops",
+    requires = [
+        crate::email_addr::emailaddr_eq,
+        emailaddr_pattern_lt, emailaddr_pattern_le, emailaddr_pattern_ge, emailaddr_pattern_gt,
+        emailaddr_pattern_cmp,
+        emailaddr_text_pattern_lt, emailaddr_text_pattern_le, emailaddr_text_pattern_ge, emailaddr_text_pattern_gt,
+        emailaddr_text_pattern_cmp,
+        emailaddr_like_prefix, emailaddr_regex_prefix, emailaddr_prefix_end,
+    ],
+);
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_like_prefix() {
+        assert_eq!(like_prefix("john%"), "john");
+        assert_eq!(like_prefix("jo_n%"), "jo");
+        assert_eq!(like_prefix("%@example.com"), "");
+        assert_eq!(like_prefix(r"john\_doe%"), "john_doe");
+        assert_eq!(like_prefix("john@example.com"), "john@example.com");
+
+        // Only the local part and the `@` can use the index
+        assert_eq!(index_prefix(like_prefix("john@Example%")), "john@");
+        assert_eq!(index_prefix(like_prefix("john%")), "john");
+    }
+
+    #[test]
+    fn test_regex_prefix() {
+        assert_eq!(regex_prefix("^john"), "john");
+        assert_eq!(regex_prefix(r"^john\.doe@"), "john.doe@");
+        assert_eq!(regex_prefix("^john.*"), "john");
+        assert_eq!(regex_prefix("^johnn?"), "john");
+        assert_eq!(regex_prefix("^johnn+"), "johnn");
+        assert_eq!(regex_prefix("^jo(hn|e)"), "");
+        assert_eq!(regex_prefix(r"^john\d"), "john");
+        assert_eq!(regex_prefix("^[jJ]ohn"), "");
+        assert_eq!(regex_prefix("john"), "");
+    }
+
+    #[pg_test]
+    fn test_pattern_ordering() {
+        use std::str::FromStr;
+        let email = |s: &str| EmailAddr::from_str(s).unwrap();
+
+        assert_eq!(pattern_cmp(&email("a@X.com"), &email("a@x.com")), Ordering::Equal);
+        assert_eq!(pattern_cmp(&email("a@bücher.de"), &email("a@xn--bcher-kva.de")), Ordering::Equal);
+        assert_eq!(pattern_cmp(&email("b@a.com"), &email("a@z.com")), Ordering::Greater);
+        assert_eq!(pattern_cmp(&email("John@a.com"), &email("john@a.com")), Ordering::Less);
+
+        assert_eq!(text_pattern_cmp(&email("john.doe@a.com"), "john"), Ordering::Greater);
+        assert_eq!(text_pattern_cmp(&email("john.doe@a.com"), &format!("john{}", char::MAX)), Ordering::Less);
+    }
+
+    #[pg_test]
+    fn test_pattern_operators() {
+        Spi::run(
+            "CREATE TABLE pattern_emails (email emailaddr); \
+             CREATE INDEX pattern_emails_idx ON pattern_emails (email emailaddr_pattern_ops); \
+             INSERT INTO pattern_emails SELECT format('user%s@example.com', i)::emailaddr FROM generate_series(1, 1000) i; \
+             INSERT INTO pattern_emails VALUES ('john@Example.com'), ('john.doe@example.org'), ('johanna@example.com'); \
+             ANALYZE pattern_emails"
+        ).unwrap();
+
+        let matching = |condition: &str| {
+            Spi::get_one::<String>(&format!(
+                "SELECT string_agg(email::text, ',' ORDER BY email::text) FROM pattern_emails WHERE {}",
+                condition
+            ))
+            .unwrap()
+            .unwrap_or_default()
+        };
+
+        assert_eq!(matching("email LIKE 'john%'"), "john.doe@example.org,john@Example.com");
+        assert_eq!(matching("email LIKE 'john@Example%'"), "john@Example.com");
+        assert_eq!(matching("email LIKE 'john@example%'"), "");
+        assert_eq!(matching("email ILIKE 'JOHN@example%'"), "john@Example.com");
+        assert_eq!(matching("email LIKE '%.org'"), "john.doe@example.org");
+        assert_eq!(matching("email NOT LIKE 'user%'"), "johanna@example.com,john.doe@example.org,john@Example.com");
+        assert_eq!(matching("email ~ '^joh?n\\.'"), "john.doe@example.org");
+        assert_eq!(matching("email ~* '^JOHANNA@'"), "johanna@example.com");
+
+        for condition in ["email LIKE 'john%'", "email ~ '^john'"] {
+            let plan = Spi::get_one::<pgrx::JsonB>(&format!(
+                "EXPLAIN (FORMAT json) SELECT * FROM pattern_emails WHERE {}",
+                condition
+            ))
+            .expect("SPI result should not be NULL")
+            .unwrap();
+            assert!(plan.0.to_string().contains("pattern_emails_idx"), "{}", condition);
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use pgrx::prelude::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::str::FromStr;
+use crate::config::{self, TableCache};
+use crate::email_addr::EmailAddr;
+
+/// Popular mail domains, most popular first: on a tie the earlier one wins
+const KNOWN_DOMAINS: &[&str] = &[
+    "gmail.com", "yahoo.com", "hotmail.com", "outlook.com", "icloud.com", "aol.com", "live.com",
+    "msn.com", "me.com", "googlemail.com", "ymail.com", "mail.com", "gmx.com", "gmx.de", "gmx.net",
+    "web.de", "yandex.ru", "mail.ru", "qq.com", "163.com", "protonmail.com", "proton.me",
+    "comcast.net", "verizon.net", "att.net", "sbcglobal.net", "yahoo.co.uk", "hotmail.co.uk",
+    "yahoo.co.jp", "yahoo.com.tw", "hinet.net", "naver.com",
+];
+
+/// Rows of a QWERTY keyboard. Each row sits half a key to the right of the
+/// one above it.
+const KEYBOARD_ROWS: &[&str] = &["1234567890-", "qwertyuiop", "asdfghjkl", "zxcvbnm,."];
+
+fn key_position(c: char) -> Option<(usize, usize)> {
+    KEYBOARD_ROWS
+        .iter()
+        .enumerate()
+        .find_map(|(row, keys)| keys.chars().position(|k| k == c).map(|col| (row, col)))
+}
+
+/// Whether `a` and `b` are next to each other on a QWERTY keyboard.
+fn keyboard_adjacent(a: char, b: char) -> bool {
+    let (Some((row_a, col_a)), Some((row_b, col_b))) = (key_position(a), key_position(b)) else {
+        return false;
+    };
+    let (col_a, col_b) = (col_a as isize, col_b as isize);
+
+    match row_b as isize - row_a as isize {
+        0 => (col_a - col_b).abs() == 1,
+        // The row below is shifted right: its keys at col - 1 and col touch
+        1 => col_b == col_a || col_b == col_a - 1,
+        -1 => col_b == col_a || col_b == col_a + 1,
+        _ => false,
+    }
+}
+
+/// Edit costs in half edits, so a slip onto a neighbouring key counts half
+const EDIT: usize = 2;
+const ADJACENT_KEY: usize = 1;
+
+/// Damerau-Levenshtein distance (optimal string alignment) between `a` and
+/// `b`, in half edits: insertions, deletions, transpositions and
+/// substitutions cost a whole edit, substituting a neighbouring key half.
+fn typo_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i * EDIT;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j * EDIT;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = match (a[i - 1], b[j - 1]) {
+                (x, y) if x == y => 0,
+                (x, y) if keyboard_adjacent(x, y) => ADJACENT_KEY,
+                _ => EDIT,
+            };
+            d[i][j] = (d[i - 1][j] + EDIT)
+                .min(d[i][j - 1] + EDIT)
+                .min(d[i - 1][j - 1] + substitution);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + EDIT);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// The known domain `domain` is most likely a typo of, if any. Short
+/// domains may be one edit away, longer ones (10 characters or more) two.
+/// Of equally close domains the one earliest in `known` wins.
+fn suggest_domain<'a>(domain: &str, known: &'a [String]) -> Option<&'a str> {
+    if known.iter().any(|k| k == domain) {
+        return None;
+    }
+
+    let max_distance = if domain.chars().count() >= 10 { 2 * EDIT } else { EDIT };
+    known
+        .iter()
+        .map(|k| (typo_distance(domain, k), k))
+        .filter(|&(distance, _)| distance <= max_distance)
+        // min_by_key keeps the first of equal distances, the more popular one
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, k)| k.as_str())
+}
+
+thread_local! {
+    static KNOWN: TableCache<Vec<String>> = const { RefCell::new(None) };
+}
+
+/// The built-in domains followed by the rows of `emailaddr_known_domains`
+/// in alphabetical order, which is the order ties are broken in. Cached per
+/// backend until the table changes.
+fn known_domains() -> Rc<Vec<String>> {
+    config::cached_table(&KNOWN, "emailaddr_known_domains", |table| {
+        let mut known: Vec<String> = KNOWN_DOMAINS.iter().map(|d| d.to_string()).collect();
+        let added = Spi::connect(|client| {
+            client
+                .select(&format!("SELECT domain FROM {} ORDER BY domain", table), None, &[])?
+                .map(|row| row.get::<String>(1))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .unwrap_or_else(|e| error!("could not read {}: {}", table, e));
+
+        for domain in added.into_iter().flatten() {
+            if !known.contains(&domain) {
+                known.push(domain);
+            }
+        }
+        known
+    })
+}
+
+extension_sql!(
+    r#"
+-- Domains emailaddr_suggest() corrects typos towards, in addition to the
+-- built-in popular mail domains
+CREATE TABLE emailaddr_known_domains (
+    domain text PRIMARY KEY CHECK (domain = lower(domain))
+);
+SELECT pg_catalog.pg_extension_config_dump('emailaddr_known_domains', '');
+
+CREATE TRIGGER emailaddr_known_domains_changed
+    AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON emailaddr_known_domains
+    FOR EACH STATEMENT EXECUTE FUNCTION pgdatatypes_plus_config_changed();
+"#,
+    name = "emailaddr_known_domains",
+    requires = [crate::config::pgdatatypes_plus_config_changed],
+);
+
+/// The address with its domain corrected, if the domain looks like a typo
+/// of a known mail domain (`gmial.com`, `hotmail.con`), otherwise NULL.
+/// The corrected address is validated like `emailaddr` input, as
+/// `emailaddr_known_domains` may hold any text; NULL if it is invalid.
+#[pg_extern(stable, parallel_safe, requires = ["emailaddr_known_domains"])]
+fn emailaddr_suggest(input: EmailAddr) -> Option<EmailAddr> {
+    // IP addresses have nothing to correct
+    input.tld()?;
+
+    let known = known_domains();
+    let domain = suggest_domain(&input.domain(), &known)?;
+    EmailAddr::from_str(&format!("{}@{}", input.local(), domain)).ok()
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyboard_adjacent() {
+        assert!(keyboard_adjacent('n', 'm'));
+        assert!(keyboard_adjacent('w', 'a'));
+        assert!(keyboard_adjacent('s', 'e'));
+        assert!(keyboard_adjacent('l', '.'));
+        assert!(!keyboard_adjacent('q', 'p'));
+        assert!(!keyboard_adjacent('a', 'e'));
+    }
+
+    #[test]
+    fn test_typo_distance() {
+        assert_eq!(typo_distance("gmail.com", "gmail.com"), 0);
+        assert_eq!(typo_distance("hotmail.con", "hotmail.com"), ADJACENT_KEY);
+        assert_eq!(typo_distance("gmial.com", "gmail.com"), EDIT);
+        assert_eq!(typo_distance("yahooo.com", "yahoo.com"), EDIT);
+        assert_eq!(typo_distance("hotmial.con", "hotmail.com"), EDIT + ADJACENT_KEY);
+    }
+
+    #[test]
+    fn test_suggest_domain() {
+        let known: Vec<String> = KNOWN_DOMAINS.iter().map(|d| d.to_string()).collect();
+
+        assert_eq!(suggest_domain("gmial.com", &known), Some("gmail.com"));
+        assert_eq!(suggest_domain("yahooo.com", &known), Some("yahoo.com"));
+        assert_eq!(suggest_domain("hotmail.con", &known), Some("hotmail.com"));
+        assert_eq!(suggest_domain("hotmial.con", &known), Some("hotmail.com"));
+        assert_eq!(suggest_domain("gmail.com", &known), None);
+        assert_eq!(suggest_domain("example.com", &known), None);
+        // Short domains only get one edit
+        assert_eq!(suggest_domain("gmxx.de", &known), Some("gmx.de"));
+        assert_eq!(suggest_domain("gmxyz.de", &known), None);
+    }
+
+    #[pg_test]
+    fn test_emailaddr_suggest() {
+        let suggest = |s: &str| {
+            Spi::get_one_with_args::<String>("SELECT emailaddr_suggest($1::emailaddr)::text", &[s.into()])
+                .unwrap()
+        };
+
+        assert_eq!(suggest("Jane.Doe@gmial.com").as_deref(), Some("Jane.Doe@gmail.com"));
+        assert_eq!(suggest("jane@Hotmail.con").as_deref(), Some("jane@hotmail.com"));
+        assert_eq!(suggest("jane@gmail.com"), None);
+        assert_eq!(suggest("jane@[127.0.0.1]"), None);
+
+        assert_eq!(suggest("jane@examplecorp.con"), None);
+        Spi::run("INSERT INTO emailaddr_known_domains VALUES ('examplecorp.com')").unwrap();
+        assert_eq!(suggest("jane@examplecorp.con").as_deref(), Some("jane@examplecorp.com"));
+
+        // A known domain that isn't valid in an address is never suggested
+        Spi::run("INSERT INTO emailaddr_known_domains VALUES ('exam_ple.com')").unwrap();
+        assert_eq!(suggest("jane@exam-ple.com"), None);
+
+        // On a tie the built-in domain wins, then the table row first in
+        // alphabetical order
+        Spi::run("INSERT INTO emailaddr_known_domains VALUES ('gmx.dt'), ('acme.it'), ('acme.in')").unwrap();
+        assert_eq!(suggest("jane@gmx.dq").as_deref(), Some("jane@gmx.de"));
+        assert_eq!(suggest("jane@acme.iz").as_deref(), Some("jane@acme.in"));
+    }
+}
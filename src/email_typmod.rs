@@ -0,0 +1,465 @@
+use pgrx::prelude::*;
+use pgrx::datum::{DatumWithOid, FromDatum, IntoDatum};
+use pgrx::pg_sys::panic::ErrorReport;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::rc::Rc;
+use crate::config::{self, TableCache};
+use crate::email_addr::{domain_argument, EmailAddr};
+use crate::error::{raise, InvalidValue};
+
+/// The type modifier of `emailaddr(254)`, `emailaddr('example.com')` or
+/// `emailaddr(254, 'example.com')`.
+///
+/// A typmod is a non-negative `i32`: the maximum length in the low 16 bits
+/// (0 for none) and the id of the domain in `emailaddr_typmod_domains`
+/// above them (0 for none).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Modifier {
+    max_length: Option<u16>,
+    domain_id: Option<i32>,
+}
+
+const LENGTH_BITS: u32 = 16;
+const LENGTH_MASK: i32 = (1 << LENGTH_BITS) - 1;
+
+const INVALID_MODIFIER: &str = "invalid type modifier for emailaddr";
+
+impl Modifier {
+    /// The modifier of `typmod`, `None` for -1 (no modifier)
+    fn from_typmod(typmod: i32) -> Option<Modifier> {
+        if typmod < 0 {
+            return None;
+        }
+        let max_length = (typmod & LENGTH_MASK) as u16;
+        let domain_id = typmod >> LENGTH_BITS;
+        Some(Modifier {
+            max_length: (max_length > 0).then_some(max_length),
+            domain_id: (domain_id > 0).then_some(domain_id),
+        })
+    }
+
+    fn typmod(self) -> i32 {
+        (self.domain_id.unwrap_or(0) << LENGTH_BITS) | self.max_length.map_or(0, i32::from)
+    }
+}
+
+/// Parses the modifiers written after `emailaddr`: a maximum length in
+/// bytes, a domain, or both in either order. The domain comes back in the
+/// form domains are compared in.
+fn parse_modifiers(modifiers: &[&str]) -> Result<(Option<u16>, Option<String>), InvalidValue> {
+    let invalid = |detail: String| {
+        InvalidValue::new(INVALID_MODIFIER)
+            .with_detail(detail)
+            .with_hint("Use emailaddr(length), emailaddr('domain') or emailaddr(length, 'domain').")
+    };
+
+    let (mut max_length, mut domain) = (None, None);
+    for &modifier in modifiers {
+        if let Ok(length) = modifier.parse::<i64>() {
+            if max_length.is_some() {
+                return Err(invalid("The length is given more than once.".to_string()));
+            }
+            let length = u16::try_from(length)
+                .ok()
+                .filter(|&length| length > 0)
+                .ok_or_else(|| invalid(format!("The length must be between 1 and {}.", u16::MAX)))?;
+            max_length = Some(length);
+        } else {
+            if domain.is_some() {
+                return Err(invalid("The domain is given more than once.".to_string()));
+            }
+            domain = Some(domain_argument(modifier).map_err(|e| invalid(e.detail.unwrap_or_default()))?);
+        }
+    }
+
+    if max_length.is_none() && domain.is_none() {
+        return Err(invalid("No modifier is given.".to_string()));
+    }
+    Ok((max_length, domain))
+}
+
+thread_local! {
+    static DOMAINS: TableCache<Vec<(i32, String)>> = const { RefCell::new(None) };
+}
+
+/// The domains type modifiers refer to, cached per backend until the
+/// table changes.
+fn registered_domains() -> Rc<Vec<(i32, String)>> {
+    config::cached_table(&DOMAINS, "emailaddr_typmod_domains", |table| {
+        Spi::connect(|client| {
+            client
+                .select(&format!("SELECT id, domain FROM {}", table), None, &[])?
+                .map(|row| Ok((row.get::<i32>(1)?.unwrap_or_default(), row.get::<String>(2)?.unwrap_or_default())))
+                .collect::<Result<Vec<_>, pgrx::spi::Error>>()
+        })
+        .unwrap_or_else(|e| error!("could not read {}: {}", table, e))
+    })
+}
+
+/// Runs `query` on `emailaddr_typmod_domains` in read-write mode, which,
+/// unlike the cached copy, sees rows added earlier in the same statement.
+fn query_registry<T: FromDatum + IntoDatum>(query: &str, args: &[DatumWithOid]) -> Option<T> {
+    let query = query.replace("$table", &config::extension_table("emailaddr_typmod_domains"));
+    Spi::connect_mut(|client| {
+        let rows = client.update(&query, None, args)?;
+        if rows.is_empty() {
+            return Ok(None);
+        }
+        rows.first().get_one::<T>()
+    })
+    .unwrap_or_else(|e| error!("could not read emailaddr_typmod_domains: {}", e))
+}
+
+fn domain_of(id: i32) -> String {
+    let cached = registered_domains()
+        .iter()
+        .find(|(registered, _)| *registered == id)
+        .map(|(_, domain)| domain.clone());
+    cached
+        .or_else(|| query_registry("SELECT domain FROM $table WHERE id = $1", &[id.into()]))
+        .unwrap_or_else(|| error!("emailaddr type modifier refers to unknown domain id {}", id))
+}
+
+/// The id of `domain`, adding it to `emailaddr_typmod_domains` the first
+/// time a type modifier names it. Adding needs INSERT on the table; using
+/// a domain already there only reads it, so works on a standby too.
+fn domain_id(domain: &str) -> i32 {
+    let registered = registered_domains()
+        .iter()
+        .find(|(_, registered)| registered == domain)
+        .map(|(id, _)| *id);
+    if let Some(id) = registered {
+        return id;
+    }
+
+    // On a conflict the row is there, added by a concurrent transaction
+    query_registry(
+        "INSERT INTO $table (domain) VALUES ($1) ON CONFLICT (domain) DO NOTHING RETURNING id",
+        &[domain.into()],
+    )
+    .or_else(|| query_registry("SELECT id FROM $table WHERE domain = $1", &[domain.into()]))
+    .unwrap_or_else(|| error!("could not register domain \"{}\" for an emailaddr type modifier", domain))
+}
+
+/// The length an address counts for: its bytes with the domain in ASCII
+/// (A-label) form, the form it takes on the wire to mail servers without
+/// SMTPUTF8 support.
+fn length(email: &EmailAddr) -> usize {
+    email.local().len() + 1 + email.domain_key().len()
+}
+
+/// Why an address doesn't fit a type modifier
+#[derive(Debug)]
+pub(crate) enum Violation {
+    TooLong { length: usize, max_length: u16 },
+    OutsideDomain { domain: String },
+}
+
+impl Violation {
+    /// `22001 string_data_right_truncation` for a length, like `varchar(n)`,
+    /// and `23514 check_violation` for a domain
+    pub(crate) fn code(&self) -> PgSqlErrorCode {
+        match self {
+            Violation::TooLong { .. } => PgSqlErrorCode::ERRCODE_STRING_DATA_RIGHT_TRUNCATION,
+            Violation::OutsideDomain { .. } => PgSqlErrorCode::ERRCODE_CHECK_VIOLATION,
+        }
+    }
+
+    pub(crate) fn report(self) -> ErrorReport {
+        let code = self.code();
+        let invalid = InvalidValue::from(self);
+        let message = invalid.reason.to_string();
+        invalid.report(code, message)
+    }
+}
+
+impl From<Violation> for InvalidValue {
+    fn from(violation: Violation) -> Self {
+        match violation {
+            Violation::TooLong { length, max_length } => InvalidValue::new("value too long for type emailaddr")
+                .with_detail(format!("The address is {} bytes long, at most {} are allowed.", length, max_length))
+                .with_hint("The length counts bytes with the domain in A-label form."),
+            Violation::OutsideDomain { domain } => InvalidValue::new("value outside the domain of type emailaddr")
+                .with_detail(format!("Only addresses at {} and its subdomains are allowed.", domain)),
+        }
+    }
+}
+
+/// Checks `email` against the type modifier `typmod` of the type it is
+/// read or cast as.
+pub(crate) fn check(email: &EmailAddr, typmod: i32) -> Result<(), Violation> {
+    let Some(modifier) = Modifier::from_typmod(typmod) else {
+        return Ok(());
+    };
+
+    if let Some(max_length) = modifier.max_length {
+        let length = length(email);
+        if length > max_length as usize {
+            return Err(Violation::TooLong { length, max_length });
+        }
+    }
+    if let Some(id) = modifier.domain_id {
+        let domain = domain_of(id);
+        if !email.in_domain(&domain) {
+            return Err(Violation::OutsideDomain { domain });
+        }
+    }
+    Ok(())
+}
+
+extension_sql!(
+    r#"
+-- The domains of emailaddr('domain') type modifiers, which refer to them
+-- by id. A domain is added the first time a type modifier names it, by a
+-- role with INSERT on the table. The table is not dumped: restoring the
+-- column definitions fills it again.
+CREATE TABLE emailaddr_typmod_domains (
+    id integer GENERATED ALWAYS AS IDENTITY (MAXVALUE 32767) PRIMARY KEY,
+    domain text NOT NULL UNIQUE
+);
+GRANT SELECT ON emailaddr_typmod_domains TO PUBLIC;
+
+CREATE TRIGGER emailaddr_typmod_domains_changed
+    AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON emailaddr_typmod_domains
+    FOR EACH STATEMENT EXECUTE FUNCTION pgdatatypes_plus_config_changed();
+
+-- Refuses to change or remove the domains of type modifiers still used by
+-- a column or a domain, which would leave them pointing at nothing
+CREATE FUNCTION emailaddr_typmod_domains_in_use() RETURNS trigger
+    SET search_path = pg_catalog, pg_temp
+    LANGUAGE plpgsql
+    AS $$
+    DECLARE
+        -- NULL checks every domain
+        domain_id integer;
+        used_by text;
+    BEGIN
+        IF TG_LEVEL = 'ROW' THEN
+            domain_id := OLD.id;
+        END IF;
+
+        SELECT string_agg(used.object, ', ' ORDER BY used.object) INTO used_by
+        FROM (
+            SELECT format('column %I of %s', attname, attrelid::regclass) AS object, atttypmod AS typmod
+            FROM pg_attribute
+            WHERE atttypid IN ('@extschema@.emailaddr'::regtype, '@extschema@.emailaddr[]'::regtype)
+                AND NOT attisdropped
+            UNION ALL
+            SELECT format('type %s', oid::regtype), typtypmod
+            FROM pg_type
+            WHERE typbasetype IN ('@extschema@.emailaddr'::regtype, '@extschema@.emailaddr[]'::regtype)
+        ) AS used
+        WHERE used.typmod >> 16 > 0
+            AND (domain_id IS NULL OR used.typmod >> 16 = domain_id);
+
+        IF used_by IS NOT NULL THEN
+            RAISE EXCEPTION 'emailaddr type modifier domain is in use'
+                USING ERRCODE = 'dependent_objects_still_exist',
+                      DETAIL = format('Used by %s.', used_by);
+        END IF;
+        RETURN OLD;
+    END
+    $$;
+
+CREATE TRIGGER emailaddr_typmod_domains_in_use
+    BEFORE UPDATE OR DELETE ON emailaddr_typmod_domains
+    FOR EACH ROW EXECUTE FUNCTION emailaddr_typmod_domains_in_use();
+
+CREATE TRIGGER emailaddr_typmod_domains_truncate
+    BEFORE TRUNCATE ON emailaddr_typmod_domains
+    FOR EACH STATEMENT EXECUTE FUNCTION emailaddr_typmod_domains_in_use();
+"#,
+    name = "emailaddr_typmod_domains",
+    requires = ["emailaddr_type", crate::config::pgdatatypes_plus_config_changed],
+);
+
+#[pg_extern(sql = false)]
+fn emailaddr_typmod_in(modifiers: Array<&CStr>) -> i32 {
+    let modifiers = modifiers
+        .iter()
+        .map(|modifier| modifier.and_then(|m| m.to_str().ok()).unwrap_or_default())
+        .collect::<Vec<_>>();
+    let (max_length, domain) = parse_modifiers(&modifiers).unwrap_or_else(|e| raise(e.parameter_report()));
+
+    let domain_id = domain.map(|domain| domain_id(&domain));
+    Modifier { max_length, domain_id }.typmod()
+}
+
+#[pg_extern(sql = false)]
+fn emailaddr_typmod_out(typmod: i32) -> CString {
+    let Some(modifier) = Modifier::from_typmod(typmod) else {
+        return CString::default();
+    };
+
+    let mut modifiers = Vec::new();
+    if let Some(max_length) = modifier.max_length {
+        modifiers.push(max_length.to_string());
+    }
+    if let Some(id) = modifier.domain_id {
+        modifiers.push(format!("'{}'", domain_of(id)));
+    }
+    // domains never contain NUL
+    CString::new(format!("({})", modifiers.join(","))).unwrap()
+}
+
+/// Coerces an address to the type modifier `typmod`, the cast PostgreSQL
+/// applies when storing into an `emailaddr(...)` column. Addresses are
+/// never truncated, so explicit casts fail like implicit ones. `IMMUTABLE`
+/// although it reads `emailaddr_typmod_domains`: the rows type modifiers
+/// use cannot change.
+#[pg_extern(immutable, parallel_safe, name = "emailaddr", requires = ["emailaddr_typmod_domains"])]
+fn emailaddr_typmod_coerce(input: EmailAddr, typmod: i32, _explicit: bool) -> Result<EmailAddr, ErrorReport> {
+    check(&input, typmod).map_err(Violation::report)?;
+    Ok(input)
+}
+
+extension_sql!(
+    r#"
+CREATE CAST (emailaddr AS emailaddr)
+    WITH FUNCTION emailaddr(emailaddr, integer, boolean) AS IMPLICIT;
+"#,
+    name = "emailaddr_typmod_cast",
+    requires = [emailaddr_typmod_coerce],
+);
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_typmod_encoding() {
+        for modifier in [
+            Modifier { max_length: Some(254), domain_id: None },
+            Modifier { max_length: None, domain_id: Some(1) },
+            Modifier { max_length: Some(u16::MAX), domain_id: Some(32767) },
+        ] {
+            assert!(modifier.typmod() >= 0);
+            assert_eq!(Modifier::from_typmod(modifier.typmod()), Some(modifier));
+        }
+        assert_eq!(Modifier::from_typmod(-1), None);
+    }
+
+    #[test]
+    fn test_parse_modifiers() {
+        assert_eq!(parse_modifiers(&["254"]), Ok((Some(254), None)));
+        assert_eq!(parse_modifiers(&["65535"]), Ok((Some(u16::MAX), None)));
+        assert_eq!(parse_modifiers(&["Example.COM."]), Ok((None, Some("example.com".to_string()))));
+        assert_eq!(
+            parse_modifiers(&["bücher.de", "100"]),
+            Ok((Some(100), Some("xn--bcher-kva.de".to_string())))
+        );
+
+        for invalid in [&["0"][..], &["-1"], &["65536"], &["10", "20"], &["a.com", "b.com"], &[""], &[]] {
+            assert!(parse_modifiers(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[pg_test]
+    fn test_length() {
+        let length = |s: &str| length(&EmailAddr::from_str(s).unwrap());
+
+        assert_eq!(length("jane@example.com"), 16);
+        // 14 characters, but 21 bytes as jane@xn--bcher-kva.de
+        assert_eq!(length("jane@bücher.de"), 21);
+        assert!(check(&EmailAddr::from_str("jane@bücher.de").unwrap(), 20).is_err());
+        assert!(check(&EmailAddr::from_str("jane@bücher.de").unwrap(), -1).is_ok());
+    }
+
+    #[pg_test]
+    fn test_in_domain() {
+        let email = |s: &str| EmailAddr::from_str(s).unwrap();
+
+        assert!(email("jane@Example.com").in_domain("example.com"));
+        assert!(email("jane@mail.example.com").in_domain("example.com"));
+        assert!(!email("jane@badexample.com").in_domain("example.com"));
+        assert!(email("jane@bücher.de").in_domain("xn--bcher-kva.de"));
+        assert!(email("jane@[127.0.0.1]").in_domain("[127.0.0.1]"));
+    }
+
+    #[pg_test]
+    fn test_typmod_columns() {
+        Spi::run(
+            "CREATE FUNCTION pg_temp.sqlstate_of(query text) RETURNS text LANGUAGE plpgsql AS $$ \
+             BEGIN EXECUTE query; RETURN NULL; \
+             EXCEPTION WHEN OTHERS THEN RETURN SQLSTATE; END $$"
+        ).unwrap();
+        let sqlstate_of = |query: &str| {
+            Spi::get_one_with_args::<String>("SELECT pg_temp.sqlstate_of($1)", &[query.into()]).unwrap()
+        };
+
+        Spi::run(
+            "CREATE TABLE typmod_emails (short emailaddr(16), corporate emailaddr(64, 'Example.com'))"
+        ).unwrap();
+        let column_types = Spi::get_one::<String>(
+            "SELECT string_agg(format_type(atttypid, atttypmod), ', ' ORDER BY attnum) \
+             FROM pg_attribute WHERE attrelid = 'typmod_emails'::regclass AND attnum > 0"
+        ).unwrap();
+        assert_eq!(column_types.as_deref(), Some("emailaddr(16), emailaddr(64,'example.com')"));
+
+        // Too long is 22001 and outside the domain 23514, whether the value
+        // is text input or cast
+        Spi::run("INSERT INTO typmod_emails VALUES ('jane@example.com', 'jane@mail.Example.com')").unwrap();
+        for query in [
+            "INSERT INTO typmod_emails (short) VALUES ('jane.doe@example.com')",
+            "INSERT INTO typmod_emails (short) SELECT 'jane.doe@example.com'::emailaddr",
+            "SELECT 'jane.doe@example.com'::emailaddr::emailaddr(16)",
+            "INSERT INTO typmod_emails (short) VALUES ('jane@bücher.de')",
+        ] {
+            assert_eq!(sqlstate_of(query).as_deref(), Some("22001"), "{}", query);
+        }
+        for query in [
+            "INSERT INTO typmod_emails (corporate) VALUES ('jane@example.org')",
+            "INSERT INTO typmod_emails (corporate) SELECT 'jane@badexample.com'::emailaddr",
+            "SELECT 'jane@example.org'::emailaddr::emailaddr('example.com')",
+        ] {
+            assert_eq!(sqlstate_of(query).as_deref(), Some("23514"), "{}", query);
+        }
+        // Invalid text is still 22P02
+        assert_eq!(sqlstate_of("INSERT INTO typmod_emails (short) VALUES ('not-an-email')").as_deref(), Some("22P02"));
+
+        let cast = Spi::get_one::<String>("SELECT 'jane@Example.com'::emailaddr::emailaddr(20)::text").unwrap();
+        assert_eq!(cast.as_deref(), Some("jane@Example.com"));
+
+        assert_eq!(sqlstate_of("SELECT 'a@b.com'::emailaddr(0)").as_deref(), Some("22023"));
+        assert_eq!(sqlstate_of("SELECT 'a@b.com'::emailaddr('a.com', 'b.com')").as_deref(), Some("22023"));
+    }
+
+    #[pg_test]
+    fn test_typmod_domains_in_use() {
+        Spi::run(
+            "CREATE DOMAIN corporate_email AS emailaddr('example.com'); \
+             CREATE TABLE partner_emails (email emailaddr('example.org')); \
+             CREATE FUNCTION pg_temp.sqlstate_of(query text) RETURNS text LANGUAGE plpgsql AS $$ \
+             BEGIN EXECUTE query; RETURN NULL; \
+             EXCEPTION WHEN OTHERS THEN RETURN SQLSTATE; END $$"
+        ).unwrap();
+        let sqlstate_of = |query: &str| {
+            Spi::get_one_with_args::<String>("SELECT pg_temp.sqlstate_of($1)", &[query.into()]).unwrap()
+        };
+
+        assert_eq!(
+            sqlstate_of("SELECT 'jane@example.net'::text::corporate_email").as_deref(),
+            Some("23514")
+        );
+
+        // Domains in use cannot be removed
+        for query in [
+            "DELETE FROM emailaddr_typmod_domains WHERE domain = 'example.com'",
+            "UPDATE emailaddr_typmod_domains SET domain = 'example.net' WHERE domain = 'example.org'",
+            "TRUNCATE emailaddr_typmod_domains",
+        ] {
+            assert_eq!(sqlstate_of(query).as_deref(), Some("2BP01"), "{}", query);
+        }
+        Spi::run("DROP TABLE partner_emails").unwrap();
+        Spi::run("DELETE FROM emailaddr_typmod_domains WHERE domain = 'example.org'").unwrap();
+
+        // Using a known domain only reads the table
+        Spi::run("SET LOCAL transaction_read_only = on").unwrap();
+        let cast = Spi::get_one::<String>("SELECT 'jane@example.com'::emailaddr('example.com')::text").unwrap();
+        assert_eq!(cast.as_deref(), Some("jane@example.com"));
+        assert_eq!(sqlstate_of("SELECT NULL::emailaddr('example.net')").as_deref(), Some("25006"));
+    }
+}
@@ -0,0 +1,701 @@
+use pgrx::prelude::*;
+use pgrx::{GucContext, GucFlags, GucRegistry, GucSetting};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use validator::ValidateEmail;
+use crate::error::InvalidValue;
+use crate::public_suffix;
+
+/// The rules `emailaddr` input is validated against, selected with
+/// `pgdatatypes_plus.email_validation`. Stored values are never revalidated,
+/// so changing the setting doesn't affect existing data, and the type input
+/// functions accept an address valid under any profile (see [`validate_any`]).
+#[derive(PostgresGucEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ValidationProfile {
+    /// The rules of the `validator` crate, after the WHATWG HTML5 email
+    /// syntax (plus IP address literals)
+    #[name = c"html5"]
+    Html5,
+    /// An SMTP mailbox (RFC 5321, section 4.1.2, with RFC 6531 UTF-8): quoted
+    /// local parts and address literals allowed, fully-qualified domain
+    /// names required
+    #[name = c"rfc5321"]
+    Rfc5321,
+    /// An RFC 5322 `addr-spec` (with RFC 6532 UTF-8), without comments and
+    /// folding white space
+    #[name = c"rfc5322"]
+    Rfc5322,
+    /// `html5`, and the domain must end in a top-level domain from the
+    /// Public Suffix List
+    #[name = c"strict_public"]
+    StrictPublic,
+}
+
+static EMAIL_VALIDATION: GucSetting<ValidationProfile> =
+    GucSetting::<ValidationProfile>::new(ValidationProfile::Html5);
+
+pub(crate) fn init() {
+    GucRegistry::define_enum_guc(
+        c"pgdatatypes_plus.email_validation",
+        c"Sets the rules emailaddr input is validated against.",
+        c"One of html5, rfc5321, rfc5322 or strict_public. Values already stored stay valid when the setting changes.",
+        &EMAIL_VALIDATION,
+        GucContext::Userset,
+        GucFlags::default(),
+    );
+}
+
+/// The profile selected for this session.
+pub(crate) fn current_profile() -> ValidationProfile {
+    EMAIL_VALIDATION.get()
+}
+
+/// The part of an address a [`Violation`] was found in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Part {
+    Address,
+    LocalPart,
+    Domain,
+}
+
+impl Part {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Part::Address => "address",
+            Part::LocalPart => "local_part",
+            Part::Domain => "domain",
+        }
+    }
+}
+
+/// Why an address is invalid: a stable reason code, the part and 1-based
+/// character position it was found at, and a message for the error DETAIL.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Violation {
+    pub code: &'static str,
+    pub part: Part,
+    pub position: Option<usize>,
+    pub message: String,
+    pub hint: Option<&'static str>,
+}
+
+impl Violation {
+    pub(crate) fn new(code: &'static str, part: Part, message: impl Into<String>) -> Self {
+        Violation {
+            code,
+            part,
+            position: None,
+            message: message.into(),
+            hint: None,
+        }
+    }
+
+    fn at(mut self, position: usize) -> Self {
+        self.position = Some(position);
+        self
+    }
+
+    fn with_hint(mut self, hint: &'static str) -> Self {
+        self.hint = Some(hint);
+        self
+    }
+}
+
+impl From<Violation> for InvalidValue {
+    fn from(violation: Violation) -> Self {
+        let invalid = InvalidValue::new(INVALID_EMAIL).with_detail(violation.message);
+        match violation.hint {
+            Some(hint) => invalid.with_hint(hint),
+            None => invalid,
+        }
+    }
+}
+
+/// 1-based character position of the byte offset `offset` in `s`
+fn char_position(s: &str, offset: usize) -> usize {
+    s[..offset].chars().count() + 1
+}
+
+/// Validates `s` under `profile`, returning the byte offset of the `@`
+/// separating the local part from the domain.
+pub(crate) fn validate(s: &str, profile: ValidationProfile) -> Result<usize, Violation> {
+    match profile {
+        ValidationProfile::Html5 => validate_html5(s),
+        ValidationProfile::Rfc5321 => validate_rfc(s, Rfc::Smtp),
+        ValidationProfile::Rfc5322 => validate_rfc(s, Rfc::AddrSpec),
+        ValidationProfile::StrictPublic => validate_strict_public(s),
+    }
+}
+
+/// Validates `s` for the type input functions, which must accept every value
+/// stored under any profile for dump/restore and binary `COPY` to work:
+/// `strict_public` only narrows `html5`, so this checks `html5`, `rfc5321`
+/// and `rfc5322`. When none accepts `s`, the violation reported is the one
+/// found under the session's profile.
+pub(crate) fn validate_any(s: &str) -> Result<usize, Violation> {
+    let reported = match current_profile() {
+        ValidationProfile::StrictPublic => ValidationProfile::Html5,
+        profile => profile,
+    };
+
+    let mut violation = None;
+    for profile in [ValidationProfile::Html5, ValidationProfile::Rfc5321, ValidationProfile::Rfc5322] {
+        match validate(s, profile) {
+            Ok(at) => return Ok(at),
+            Err(v) if profile == reported => violation = Some(v),
+            Err(_) => {}
+        }
+    }
+    Err(violation.expect("the reported profile is always checked"))
+}
+
+fn validate_html5(s: &str) -> Result<usize, Violation> {
+    if !s.validate_email() {
+        return Err(describe_invalid_email(s));
+    }
+    // The domain never contains `@`
+    s.rfind('@').ok_or_else(|| describe_invalid_email(s))
+}
+
+fn validate_strict_public(s: &str) -> Result<usize, Violation> {
+    let at = validate_html5(s)?;
+    let domain = &s[at + 1..];
+
+    let Some((_, tld)) = domain.rsplit_once('.') else {
+        return Err(Violation::new(
+            "missing_tld",
+            Part::Domain,
+            format!("The domain \"{}\" has no top-level domain.", domain),
+        )
+        .at(char_position(s, at + 1)));
+    };
+    if domain.starts_with('[') || tld.bytes().all(|c| c.is_ascii_digit()) {
+        return Err(Violation::new(
+            "ip_address_domain",
+            Part::Domain,
+            "The domain is an IP address, a domain name is required.",
+        )
+        .at(char_position(s, at + 1)));
+    }
+
+    // The list spells internationalised TLDs in Unicode
+    let position = char_position(s, s.len() - tld.len());
+    let tld = idna::domain_to_unicode(tld).0;
+    if !public_suffix::current_list().has_tld(&tld) {
+        return Err(Violation::new(
+            "unknown_tld",
+            Part::Domain,
+            format!("\"{}\" is not a public top-level domain.", tld),
+        )
+        .at(position)
+        .with_hint("Check the domain for typing errors."));
+    }
+    Ok(at)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Rfc {
+    /// RFC 5321 `Mailbox`
+    Smtp,
+    /// RFC 5322 `addr-spec`
+    AddrSpec,
+}
+
+/// `atext` of RFC 5322, extended to UTF-8 by RFC 6532
+fn is_atext(c: char) -> bool {
+    c.is_ascii_alphanumeric() || LOCAL_PART_SPECIALS.contains(c) || !c.is_ascii()
+}
+
+/// Checks `s` against the RFC 5321 or RFC 5322 grammar.
+fn validate_rfc(s: &str, rfc: Rfc) -> Result<usize, Violation> {
+    let at = if s.starts_with('"') {
+        let end = quoted_string_end(s).ok_or_else(|| {
+            Violation::new(
+                "unterminated_quote",
+                Part::LocalPart,
+                "The quoted local part has no closing quote.",
+            )
+            .at(1)
+        })?;
+        if !s[end..].starts_with('@') {
+            return Err(Violation::new(
+                "invalid_character",
+                Part::LocalPart,
+                format!(
+                    "The quoted local part must be followed by \"@\", at position {}.",
+                    char_position(s, end)
+                ),
+            )
+            .at(char_position(s, end)));
+        }
+        end
+    } else {
+        s.find('@').ok_or_else(|| {
+            Violation::new(
+                "missing_at",
+                Part::Address,
+                "The address has no \"@\" separating the local part from the domain.",
+            )
+        })?
+    };
+    let (local, domain) = (&s[..at], &s[at + 1..]);
+    let domain_position = char_position(s, at + 1);
+
+    if local.is_empty() {
+        return Err(Violation::new("empty_local_part", Part::LocalPart, "The local part before \"@\" is empty.").at(1));
+    }
+    if domain.is_empty() {
+        return Err(Violation::new("empty_domain", Part::Domain, "The domain after \"@\" is empty.").at(domain_position));
+    }
+    if local.len() > 64 {
+        return Err(Violation::new(
+            "local_part_too_long",
+            Part::LocalPart,
+            format!("The local part is {} octets long, the maximum is 64.", local.len()),
+        )
+        .at(1));
+    }
+    if domain.len() > 255 {
+        return Err(Violation::new(
+            "domain_too_long",
+            Part::Domain,
+            format!("The domain is {} octets long, the maximum is 255.", domain.len()),
+        )
+        .at(domain_position));
+    }
+
+    if local.starts_with('"') {
+        let inner = &local[1..local.len() - 1];
+        let mut chars = inner.char_indices();
+        while let Some((i, c)) = chars.next() {
+            let allowed = match c {
+                // quoted-pair
+                '\\' => match chars.next() {
+                    Some((_, escaped)) => {
+                        escaped == ' '
+                            || (escaped == '\t' && rfc == Rfc::AddrSpec)
+                            || escaped.is_ascii_graphic()
+                            || !escaped.is_ascii()
+                    }
+                    None => false,
+                },
+                ' ' => true,
+                '\t' => rfc == Rfc::AddrSpec,
+                c => (c.is_ascii_graphic() && c != '"') || !c.is_ascii(),
+            };
+            if !allowed {
+                let position = char_position(s, 1 + i);
+                return Err(Violation::new(
+                    "invalid_character",
+                    Part::LocalPart,
+                    format!(
+                        "The quoted local part contains the character \"{}\" at position {}, which is not allowed.",
+                        c.escape_debug(),
+                        position
+                    ),
+                )
+                .at(position));
+            }
+        }
+    } else {
+        check_dot_atom(s, 0, local, Part::LocalPart)?;
+    }
+
+    if let Some(literal) = domain.strip_prefix('[') {
+        let Some(literal) = literal.strip_suffix(']') else {
+            return Err(Violation::new(
+                "invalid_address_literal",
+                Part::Domain,
+                "The address literal has no closing \"]\".",
+            )
+            .at(domain_position));
+        };
+        let valid = match rfc {
+            Rfc::Smtp => match literal.strip_prefix("IPv6:") {
+                Some(v6) => v6.parse::<Ipv6Addr>().is_ok(),
+                None => literal.parse::<Ipv4Addr>().is_ok(),
+            },
+            // dtext: printable ASCII except `[`, `]` and `\`
+            Rfc::AddrSpec => literal
+                .chars()
+                .all(|c| c.is_ascii_graphic() && !matches!(c, '[' | ']' | '\\')),
+        };
+        if !valid {
+            let violation = Violation::new(
+                "invalid_address_literal",
+                Part::Domain,
+                format!("\"{}\" is not a valid address literal.", domain),
+            )
+            .at(domain_position);
+            return Err(if rfc == Rfc::Smtp {
+                violation.with_hint("IPv6 address literals are written as [IPv6:2001:db8::1].")
+            } else {
+                violation
+            });
+        }
+        return Ok(at);
+    }
+
+    match rfc {
+        Rfc::AddrSpec => check_dot_atom(s, at + 1, domain, Part::Domain)?,
+        Rfc::Smtp => check_smtp_domain(s, at + 1, domain)?,
+    }
+    Ok(at)
+}
+
+/// The byte offset just past the closing quote of the quoted string `s`
+/// starts with.
+fn quoted_string_end(s: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in s.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return Some(i + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Checks a `dot-atom`: `atext` runs separated by single dots. `text` is the
+/// `part` of the address `s` starting at byte offset `offset`.
+fn check_dot_atom(s: &str, offset: usize, text: &str, part: Part) -> Result<(), Violation> {
+    let name = match part {
+        Part::LocalPart => "local part",
+        _ => "domain",
+    };
+    let position = |i: usize| char_position(s, offset + i);
+
+    if part == Part::Domain && text.ends_with('.') {
+        return Err(Violation::new("trailing_dot", part, "The domain ends with a dot.")
+            .at(position(text.len() - 1))
+            .with_hint("Remove the trailing dot."));
+    }
+    if text.starts_with('.') || text.ends_with('.') {
+        let i = if text.starts_with('.') { 0 } else { text.len() - 1 };
+        return Err(Violation::new("misplaced_dot", part, format!("The {} starts or ends with a dot.", name))
+            .at(position(i)));
+    }
+    if let Some(i) = text.find("..") {
+        return Err(Violation::new(
+            "misplaced_dot",
+            part,
+            format!("The {} has two consecutive dots at position {}.", name, position(i)),
+        )
+        .at(position(i)));
+    }
+    if let Some((i, c)) = text.char_indices().find(|&(_, c)| c != '.' && !is_atext(c)) {
+        return Err(Violation::new(
+            "invalid_character",
+            part,
+            format!(
+                "The {} contains the character \"{}\" at position {}, which is not allowed.",
+                name,
+                c.escape_debug(),
+                position(i)
+            ),
+        )
+        .at(position(i)));
+    }
+    Ok(())
+}
+
+/// Checks an RFC 5321 `Domain`: a fully-qualified name of letter, digit and
+/// hyphen labels (or U-labels, RFC 6531). `domain` starts at byte offset
+/// `offset` of the address `s`.
+fn check_smtp_domain(s: &str, offset: usize, domain: &str) -> Result<(), Violation> {
+    if domain.ends_with('.') {
+        return Err(Violation::new("trailing_dot", Part::Domain, "The domain ends with a dot.")
+            .at(char_position(s, s.len() - 1))
+            .with_hint("Remove the trailing dot."));
+    }
+    if !domain.contains('.') {
+        return Err(Violation::new(
+            "not_fully_qualified",
+            Part::Domain,
+            format!("The domain \"{}\" is not fully qualified.", domain),
+        )
+        .at(char_position(s, offset))
+        .with_hint("RFC 5321 only permits fully-qualified domain names."));
+    }
+
+    let mut label_offset = offset;
+    for label in domain.split('.') {
+        check_label(s, label_offset, label, |c| c.is_ascii_alphanumeric() || c == '-')?;
+        label_offset += label.len() + 1;
+    }
+    Ok(())
+}
+
+/// Checks one domain label starting at byte offset `offset` of the address
+/// `s`: not empty, at most 63 long, no hyphen at either end, and only ASCII
+/// characters `allowed` (non-ASCII is left to IDNA).
+fn check_label(s: &str, offset: usize, label: &str, allowed: impl Fn(char) -> bool) -> Result<(), Violation> {
+    let position = char_position(s, offset);
+    let label_len = label.chars().count();
+
+    if label.is_empty() {
+        return Err(Violation::new(
+            "empty_label",
+            Part::Domain,
+            format!("The domain has an empty label at position {}.", position),
+        )
+        .at(position));
+    }
+    if label_len > 63 {
+        return Err(Violation::new(
+            "label_too_long",
+            Part::Domain,
+            format!("The domain label \"{}\" is {} characters long, the maximum is 63.", label, label_len),
+        )
+        .at(position));
+    }
+    if label.starts_with('-') || label.ends_with('-') {
+        return Err(Violation::new(
+            "misplaced_hyphen",
+            Part::Domain,
+            format!("The domain label \"{}\" starts or ends with a hyphen.", label),
+        )
+        .at(position));
+    }
+
+    let bad_char = label
+        .chars()
+        .enumerate()
+        .find(|&(_, c)| c.is_ascii() && !allowed(c));
+    if let Some((i, c)) = bad_char {
+        let code = if c == '_' { "underscore_in_domain" } else { "invalid_character" };
+        let violation = Violation::new(
+            code,
+            Part::Domain,
+            format!(
+                "The domain contains the character \"{}\" at position {}, which is not allowed.",
+                c.escape_debug(),
+                position + i
+            ),
+        )
+        .at(position + i);
+        return Err(if c == '_' {
+            violation.with_hint("Underscores are not allowed in domain names.")
+        } else {
+            violation
+        });
+    }
+    Ok(())
+}
+
+pub(crate) const INVALID_EMAIL: &str = "invalid email address format";
+
+/// Characters allowed unquoted in the local part, besides ASCII letters,
+/// digits and the dot
+const LOCAL_PART_SPECIALS: &str = "!#$%&'*+/=?^_`{|}~-";
+
+/// Works out why `validate_email` rejected `s`. The checks follow the rules
+/// of the `validator` crate.
+fn describe_invalid_email(s: &str) -> Violation {
+    let Some(at) = s.rfind('@') else {
+        return Violation::new(
+            "missing_at",
+            Part::Address,
+            "The address has no \"@\" separating the local part from the domain.",
+        );
+    };
+    let (local, domain) = (&s[..at], &s[at + 1..]);
+    let local_len = local.chars().count();
+    let domain_len = domain.chars().count();
+    let domain_position = local_len + 2;
+
+    if local.is_empty() {
+        return Violation::new("empty_local_part", Part::LocalPart, "The local part before \"@\" is empty.").at(1);
+    }
+    if domain.is_empty() {
+        return Violation::new("empty_domain", Part::Domain, "The domain after \"@\" is empty.").at(domain_position);
+    }
+    if local_len > 64 {
+        return Violation::new(
+            "local_part_too_long",
+            Part::LocalPart,
+            format!("The local part is {} characters long, the maximum is 64.", local_len),
+        )
+        .at(1);
+    }
+    if domain_len > 255 {
+        return Violation::new(
+            "domain_too_long",
+            Part::Domain,
+            format!("The domain is {} characters long, the maximum is 255.", domain_len),
+        )
+        .at(domain_position);
+    }
+
+    let bad_local_char = local
+        .chars()
+        .enumerate()
+        .find(|&(_, c)| !(c.is_ascii_alphanumeric() || c == '.' || LOCAL_PART_SPECIALS.contains(c)));
+    if let Some((i, c)) = bad_local_char {
+        let violation = Violation::new(
+            "invalid_character",
+            Part::LocalPart,
+            format!(
+                "The local part contains the character \"{}\" at position {}, which is not allowed.",
+                c.escape_debug(),
+                i + 1
+            ),
+        )
+        .at(i + 1);
+        return if c == '"' {
+            violation.with_hint("Quoted local parts need the rfc5321 or rfc5322 validation profile.")
+        } else {
+            violation
+        };
+    }
+
+    if domain.starts_with('[') {
+        return Violation::new(
+            "invalid_address_literal",
+            Part::Domain,
+            format!("\"{}\" is not a valid IP address literal.", domain),
+        )
+        .at(domain_position);
+    }
+    if domain.ends_with('.') {
+        return Violation::new("trailing_dot", Part::Domain, "The domain ends with a dot.")
+            .at(local_len + 1 + domain_len)
+            .with_hint("Remove the trailing dot.");
+    }
+
+    let mut label_offset = at + 1;
+    for label in domain.split('.') {
+        if let Err(violation) = check_label(s, label_offset, label, |c| c.is_ascii_alphanumeric() || c == '-') {
+            return violation;
+        }
+        label_offset += label.len() + 1;
+    }
+
+    Violation::new(
+        "invalid_format",
+        Part::Address,
+        "The address does not match the email address syntax.",
+    )
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles() {
+        use ValidationProfile::*;
+
+        let tests = [
+            // address, html5, rfc5321, rfc5322
+            ("user@example.com", true, true, true),
+            ("abc@bar", true, false, true),
+            (r#""john doe"@example.com"#, false, true, true),
+            (r#""john@home"@example.com"#, false, true, true),
+            (r#""a\"b"@example.com"#, false, true, true),
+            (r#""unterminated@example.com"#, false, false, false),
+            ("user@[127.0.0.1]", true, true, true),
+            ("user@[IPv6:2001:db8::1]", false, true, true),
+            ("user@[2001:db8::1]", true, false, true),
+            ("user@[not an ip]", false, false, false),
+            // The validator crate doesn't check dot placement
+            ("a..b@example.com", true, false, false),
+            (".ab@example.com", true, false, false),
+            ("user@exam_ple.com", false, false, true),
+            ("user@-example.com", false, false, true),
+            ("user@example.com.", false, false, false),
+            ("user@bücher.de", true, true, true),
+            ("no-at-sign", false, false, false),
+        ];
+
+        for (address, html5, rfc5321, rfc5322) in tests {
+            assert_eq!(validate(address, Html5).is_ok(), html5, "html5: {}", address);
+            assert_eq!(validate(address, Rfc5321).is_ok(), rfc5321, "rfc5321: {}", address);
+            assert_eq!(validate(address, Rfc5322).is_ok(), rfc5322, "rfc5322: {}", address);
+        }
+
+        // The `@` offset skips one inside a quoted local part
+        assert_eq!(validate(r#""a@b"@example.com"#, Rfc5322), Ok(5));
+    }
+
+    #[test]
+    fn test_profile_details() {
+        let detail = |s: &str, profile| Some(validate(s, profile).unwrap_err().message);
+
+        assert_eq!(
+            detail("abc@bar", ValidationProfile::Rfc5321).as_deref(),
+            Some("The domain \"bar\" is not fully qualified.")
+        );
+        assert_eq!(
+            detail("a..b@example.com", ValidationProfile::Rfc5322).as_deref(),
+            Some("The local part has two consecutive dots at position 2.")
+        );
+        assert_eq!(
+            detail("\"a\u{1}\"@example.com", ValidationProfile::Rfc5321).as_deref(),
+            Some("The quoted local part contains the character \"\\u{1}\" at position 3, which is not allowed.")
+        );
+    }
+
+    #[pg_test]
+    fn test_validate_any() {
+        assert_eq!(validate_any("abc@bar"), Ok(3));
+        assert_eq!(validate_any(r#""john doe"@example.com"#), Ok(10));
+        assert_eq!(validate_any("user@[IPv6:2001:db8::1]"), Ok(4));
+        assert_eq!(validate_any("user@exam_ple.com"), Ok(4));
+        assert!(validate_any("user@example.com.").is_err());
+        assert!(validate_any("no-at-sign").is_err());
+    }
+
+    #[pg_test]
+    fn test_strict_public() {
+        let strict = |s: &str| validate(s, ValidationProfile::StrictPublic);
+
+        assert!(strict("user@example.com").is_ok());
+        assert!(strict("user@example.co.uk").is_ok());
+        assert!(strict("user@bücher.de").is_ok());
+        assert!(strict("user@xn--p1b6ci4b4b3a.xn--11b5bs3a9aj6g").is_ok());
+        assert!(strict("user@example.internal").is_err());
+        assert!(strict("abc@bar").is_err());
+        assert!(strict("user@[127.0.0.1]").is_err());
+        assert!(strict("user@127.0.0.1").is_err());
+    }
+
+    #[pg_test]
+    fn test_profile_guc() {
+        Spi::run("CREATE TABLE profile_emails (email emailaddr)").unwrap();
+        Spi::run("INSERT INTO profile_emails VALUES ('abc@bar')").unwrap();
+
+        Spi::run("SET pgdatatypes_plus.email_validation = 'rfc5322'").unwrap();
+        let quoted = Spi::get_one::<String>(r#"SELECT emailaddr('"john doe"@example.com')::text"#)
+            .expect("SPI result should not be NULL").unwrap();
+        assert_eq!(quoted, r#""john doe"@example.com"#);
+
+        Spi::run("SET pgdatatypes_plus.email_validation = 'strict_public'").unwrap();
+        assert_eq!(current_profile(), ValidationProfile::StrictPublic);
+        Spi::run(
+            "CREATE FUNCTION pg_temp.sqlstate_of(query text) RETURNS text LANGUAGE plpgsql AS $$ \
+             BEGIN EXECUTE query; RETURN NULL; \
+             EXCEPTION WHEN OTHERS THEN RETURN SQLSTATE; END $$"
+        ).unwrap();
+        let sqlstate = Spi::get_one::<String>("SELECT pg_temp.sqlstate_of('SELECT ''abc@bar''::text::emailaddr')")
+            .expect("SPI result should not be NULL").unwrap();
+        assert_eq!(sqlstate, "22P02");
+        let sqlstate = Spi::get_one::<String>("SELECT pg_temp.sqlstate_of('SELECT emailaddr(''abc@bar'')')")
+            .expect("SPI result should not be NULL").unwrap();
+        assert_eq!(sqlstate, "22P02");
+
+        // Stored values are not revalidated, and type input accepts them
+        // for dump/restore
+        let stored = Spi::get_one::<String>("SELECT email::text FROM profile_emails")
+            .expect("SPI result should not be NULL").unwrap();
+        assert_eq!(stored, "abc@bar");
+        let restored = Spi::get_one::<String>("SELECT 'abc@bar'::emailaddr::text")
+            .expect("SPI result should not be NULL").unwrap();
+        assert_eq!(restored, "abc@bar");
+
+        Spi::run("RESET pgdatatypes_plus.email_validation").unwrap();
+        assert_eq!(current_profile(), ValidationProfile::Html5);
+    }
+}
@@ -0,0 +1,81 @@
+use pgrx::pg_sys::panic::ErrorReport;
+use pgrx::prelude::*;
+use std::fmt::{self, Display};
+
+/// Why a value was rejected, with the DETAIL and HINT fields to report
+/// alongside it. `Display` shows only the short reason.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidValue {
+    pub reason: &'static str,
+    pub detail: Option<String>,
+    pub hint: Option<String>,
+}
+
+impl InvalidValue {
+    pub fn new(reason: &'static str) -> Self {
+        InvalidValue {
+            reason,
+            detail: None,
+            hint: None,
+        }
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    /// Builds the report for bad text input to `type_name`,
+    /// `22P02 invalid_text_representation`.
+    pub fn input_report(self, type_name: &str) -> ErrorReport {
+        self.report(
+            PgSqlErrorCode::ERRCODE_INVALID_TEXT_REPRESENTATION,
+            format!("invalid input syntax for type {}: {}", type_name, self.reason),
+        )
+    }
+
+    /// Builds the report for bad binary input to `type_name`,
+    /// `22P03 invalid_binary_representation`.
+    pub fn binary_input_report(self, type_name: &str) -> ErrorReport {
+        self.report(
+            PgSqlErrorCode::ERRCODE_INVALID_BINARY_REPRESENTATION,
+            format!("invalid binary input for type {}: {}", type_name, self.reason),
+        )
+    }
+
+    /// Builds the report for a bad function argument,
+    /// `22023 invalid_parameter_value`.
+    pub fn parameter_report(self) -> ErrorReport {
+        let message = self.reason.to_string();
+        self.report(PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE, message)
+    }
+
+    /// Builds a report with `code` and `message`, and the detail and hint.
+    pub fn report(self, code: PgSqlErrorCode, message: String) -> ErrorReport {
+        let mut report = ErrorReport::new(code, message, pgrx::function_name!());
+        if let Some(detail) = self.detail {
+            report = report.set_detail(detail);
+        }
+        if let Some(hint) = self.hint {
+            report = report.set_hint(hint);
+        }
+        report
+    }
+}
+
+impl Display for InvalidValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.reason)
+    }
+}
+
+/// Raises `report` as an ERROR.
+pub fn raise(report: ErrorReport) -> ! {
+    report.report(PgLogLevel::ERROR);
+    unreachable!()
+}
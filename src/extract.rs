@@ -0,0 +1,250 @@
+use pgrx::prelude::*;
+use std::str::FromStr;
+use crate::email_addr::EmailAddr;
+use crate::twid::Twid;
+
+/// Characters of a local part found in free text. Quoted local parts and
+/// the rarer RFC 5322 symbols are left out: in prose they are far more
+/// often punctuation (`mailto:a@x?cc=b@y`, `'a@x'`) than part of an address.
+fn is_local_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '+' | '-')
+}
+
+/// Characters of a domain found in free text, including internationalised
+/// labels
+fn is_domain_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '.' | '-')
+}
+
+/// Counts characters from byte offsets, for reporting positions in
+/// characters while scanning by byte
+struct CharCounter<'a> {
+    text: &'a str,
+    byte: usize,
+    chars: usize,
+}
+
+impl<'a> CharCounter<'a> {
+    fn new(text: &'a str) -> CharCounter<'a> {
+        CharCounter { text, byte: 0, chars: 0 }
+    }
+
+    /// The 1-based character position of byte offset `byte`. Offsets must
+    /// be asked for in increasing order.
+    fn position(&mut self, byte: usize) -> usize {
+        self.chars += self.text[self.byte..byte].chars().count();
+        self.byte = byte;
+        self.chars + 1
+    }
+}
+
+/// A valid address found in text, with the byte range it was found at
+#[derive(Debug)]
+struct Found<T> {
+    value: T,
+    start: usize,
+    end: usize,
+}
+
+/// The byte range of the address candidate around the `@` at `at`, not
+/// reaching back before `floor`
+fn email_candidate(text: &str, at: usize, floor: usize) -> (usize, usize) {
+    let start = text[floor..at]
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| is_local_char(c))
+        .last()
+        .map_or(at, |(i, _)| floor + i);
+    // A sentence may run into the address: `...see:.john@x`
+    let start = start + text[start..at].len() - text[start..at].trim_start_matches('.').len();
+
+    let domain = &text[at + 1..];
+    let mut end = at + 1 + domain.find(|c| !is_domain_char(c)).unwrap_or(domain.len());
+    // Trailing punctuation ends a sentence, not the domain
+    end = at + 1 + text[at + 1..end].trim_end_matches(['.', '-']).len();
+
+    // A last label mixing ASCII and other letters is an ASCII TLD followed by
+    // text, as in `mei@example.tw謝謝`; internationalised TLDs are all non-ASCII
+    let tld_start = text[at + 1..end].rfind('.').map_or(at + 1, |dot| at + 2 + dot);
+    let tld = &text[tld_start..end];
+    if tld.starts_with(|c: char| c.is_ascii()) {
+        if let Some(i) = tld.find(|c: char| !c.is_ascii()) {
+            end = tld_start + i;
+        }
+    }
+
+    (start, end)
+}
+
+/// The valid addresses in `text`, in order. Addresses are delimited by
+/// characters that can't be part of one, so `mailto:` prefixes, angle
+/// brackets, quotes and trailing punctuation are left out.
+fn find_emailaddrs(text: &str) -> Vec<Found<EmailAddr>> {
+    let mut found = Vec::new();
+    let mut floor = 0;
+    for (at, _) in text.match_indices('@') {
+        if at < floor {
+            continue;
+        }
+        let (start, end) = email_candidate(text, at, floor);
+        if start == at || end == at + 1 {
+            continue;
+        }
+        if let Ok(value) = EmailAddr::from_str(&text[start..end]) {
+            found.push(Found { value, start, end });
+            floor = end;
+        }
+    }
+    found
+}
+
+/// The valid Taiwan National IDs in `text`, in order: a region letter and
+/// nine digits, standing alone as a word and passing the checksum.
+fn find_twids(text: &str) -> Vec<Found<Twid>> {
+    let bytes = text.as_bytes();
+    // Only ASCII: in Chinese text an ID often follows the words directly
+    let is_word_char = |c: Option<char>| c.is_some_and(|c| c.is_ascii_alphanumeric());
+
+    let mut found = Vec::new();
+    let mut start = 0;
+    while start + 10 <= bytes.len() {
+        let end = start + 10;
+        let candidate = bytes[start].is_ascii_alphabetic()
+            && bytes[start + 1..end].iter().all(u8::is_ascii_digit)
+            && !is_word_char(text[..start].chars().next_back())
+            && !is_word_char(text.get(end..).and_then(|rest| rest.chars().next()));
+
+        // The checksum is the one is_valid_twid applies, which weeds out
+        // most letter-and-digit tokens
+        let twid = if candidate { Twid::from_str(&text[start..end]).ok() } else { None };
+        match twid {
+            Some(value) => {
+                found.push(Found { value, start, end });
+                start = end;
+            }
+            None => start += text[start..].chars().next().map_or(1, char::len_utf8),
+        }
+    }
+    found
+}
+
+/// The valid email addresses in `text`, with the 1-based character
+/// position and length of each, for finding and redacting addresses in
+/// free text. `mailto:` prefixes and trailing punctuation are not part of
+/// a match.
+#[pg_extern(stable, parallel_safe)]
+fn extract_emailaddrs(
+    text: &str,
+) -> TableIterator<'static, (name!(address, EmailAddr), name!(position, i32), name!(length, i32))> {
+    let mut counter = CharCounter::new(text);
+    let rows = find_emailaddrs(text)
+        .into_iter()
+        .map(|found| {
+            let position = counter.position(found.start);
+            let length = text[found.start..found.end].chars().count();
+            (found.value, position as i32, length as i32)
+        })
+        .collect::<Vec<_>>();
+    TableIterator::new(rows)
+}
+
+/// The valid Taiwan National IDs in `text`, with the 1-based character
+/// position and length of each. Only IDs that pass the checksum are
+/// returned.
+#[pg_extern(immutable, parallel_safe)]
+fn extract_twids(
+    text: &str,
+) -> TableIterator<'static, (name!(twid, Twid), name!(position, i32), name!(length, i32))> {
+    let mut counter = CharCounter::new(text);
+    let rows = find_twids(text)
+        .into_iter()
+        .map(|found| (found.value, counter.position(found.start) as i32, (found.end - found.start) as i32))
+        .collect::<Vec<_>>();
+    TableIterator::new(rows)
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+
+    /// The matched text of each candidate around an `@`
+    fn candidates(text: &str) -> Vec<&str> {
+        text.match_indices('@')
+            .map(|(at, _)| {
+                let (start, end) = email_candidate(text, at, 0);
+                &text[start..end]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_email_candidates() {
+        assert_eq!(candidates("Mail john.doe@example.com."), ["john.doe@example.com"]);
+        assert_eq!(candidates("(john@example.com)"), ["john@example.com"]);
+        assert_eq!(candidates("mailto:john@example.com?subject=hi"), ["john@example.com"]);
+        assert_eq!(candidates("<a+tag@example.co.uk>, 'b@example.org'"), ["a+tag@example.co.uk", "b@example.org"]);
+        assert_eq!(candidates("see:.john@example.com-"), ["john@example.com"]);
+        assert_eq!(candidates("請寄到mei@example.tw謝謝"), ["mei@example.tw"]);
+        assert_eq!(candidates("jane@bücher.de!"), ["jane@bücher.de"]);
+        assert_eq!(candidates("@handle and a@"), ["@handle", "a@"]);
+    }
+
+    #[test]
+    fn test_char_counter() {
+        let text = "陳美 a@b.tw 和 c@d.tw";
+        let mut counter = CharCounter::new(text);
+        assert_eq!(counter.position(0), 1);
+        assert_eq!(counter.position(text.find('a').unwrap()), 4);
+        assert_eq!(counter.position(text.find('c').unwrap()), 13);
+    }
+
+    #[pg_test]
+    fn test_find_emailaddrs() {
+        let found = |text: &str| {
+            find_emailaddrs(text)
+                .into_iter()
+                .map(|found| found.value.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            found("Contact john.doe@example.com, or mailto:jane@example.org?cc=ops@example.org."),
+            ["john.doe@example.com", "jane@example.org", "ops@example.org"]
+        );
+        assert_eq!(found("請寄到 mei@example.tw 謝謝"), ["mei@example.tw"]);
+        assert_eq!(found("@handle, 50@ 3 each, a@-"), Vec::<String>::new());
+    }
+
+    #[pg_test]
+    fn test_find_twids() {
+        let found = |text: &str| {
+            find_twids(text)
+                .into_iter()
+                .map(|found| found.value.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(found("ID: A123456789, f131232216."), ["A123456789", "F131232216"]);
+        // Fails the checksum
+        assert_eq!(found("A123456788"), Vec::<String>::new());
+        // Part of a longer token
+        assert_eq!(found("XA123456789 A1234567890 A123456789X"), Vec::<String>::new());
+        assert_eq!(found("身分證字號A123456789。"), ["A123456789"]);
+    }
+
+    #[pg_test]
+    fn test_extract_functions() {
+        let rows = Spi::get_one::<String>(
+            "SELECT string_agg(format('%s:%s:%s', address, position, length), ',' ORDER BY position) \
+             FROM extract_emailaddrs('陳美 <mei@example.tw>; mailto:jane@example.org.')"
+        ).unwrap();
+        assert_eq!(rows.as_deref(), Some("mei@example.tw:5:14,jane@example.org:29:16"));
+
+        let rows = Spi::get_one::<String>(
+            "SELECT string_agg(format('%s:%s', twid, position), ',' ORDER BY position) \
+             FROM extract_twids('身分證 a123456789、A123456788')"
+        ).unwrap();
+        assert_eq!(rows.as_deref(), Some("A123456789:5"));
+    }
+}
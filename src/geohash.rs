@@ -1,13 +1,38 @@
-use std::error::Error;
 use pgrx::prelude::*;
+use pgrx::pg_sys::panic::ErrorReport;
 use pgrx::pg_sys::Point;
-use geohash::{encode, decode, neighbor, neighbors, Direction, Coord};
+use geohash::{encode, decode, neighbor, neighbors, Direction, Coord, GeohashError};
+use crate::error::InvalidValue;
+
+/// Maps errors from the geohash crate to reports with a proper SQLSTATE:
+/// malformed geohash text is `22P02`, out-of-range arguments are `22023`.
+/// Geohashes are plain `text`, so input errors name that type.
+fn geohash_error(e: GeohashError) -> ErrorReport {
+    match e {
+        GeohashError::InvalidHashCharacter(c) => InvalidValue::new("invalid geohash")
+            .with_detail(format!("\"{}\" is not a valid geohash character.", c))
+            .with_hint("Geohashes use the characters 0-9 and b-z, except i, l and o.")
+            .input_report("text"),
+        GeohashError::InvalidHash(msg) => InvalidValue::new("invalid geohash")
+            .with_detail(msg)
+            .input_report("text"),
+        GeohashError::InvalidCoordinateRange(c) => InvalidValue::new("coordinate out of range")
+            .with_detail(format!(
+                "Longitude must be between -180 and 180 and latitude between -90 and 90, got ({}, {}).",
+                c.x, c.y
+            ))
+            .parameter_report(),
+        GeohashError::InvalidLength(len) => InvalidValue::new("Precision must be between 1 and 12")
+            .with_detail(format!("Got {}.", len))
+            .parameter_report(),
+    }
+}
 
 /// Encode a coordinate to geohash with default precision of 12
 #[pg_extern]
-fn geohash_encode(point: Point) -> Result<String, Box<dyn Error + Send + Sync>> {
+fn geohash_encode(point: Point) -> Result<String, ErrorReport> {
     let coord = Coord { x: point.x, y: point.y };
-    encode(coord, 12).map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)
+    encode(coord, 12).map_err(geohash_error)
 }
 
 /// Encode a coordinate to geohash with specified precision
@@ -15,19 +40,21 @@ fn geohash_encode(point: Point) -> Result<String, Box<dyn Error + Send + Sync>>
 fn geohash_encode_with_precision(
     point: Point, 
     precision: i32
-) -> Result<String, Box<dyn Error + Send + Sync>> {
+) -> Result<String, ErrorReport> {
     if precision < 1 || precision > 12 {
-        return Err("Precision must be between 1 and 12".into());
+        return Err(InvalidValue::new("Precision must be between 1 and 12")
+            .with_detail(format!("Got {}.", precision))
+            .parameter_report());
     }
     
     let coord = Coord { x: point.x, y: point.y };
-    encode(coord, precision as usize).map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)
+    encode(coord, precision as usize).map_err(geohash_error)
 }
 
 /// Decode a geohash string to a coordinate point
 #[pg_extern]
-fn geohash_decode(hash_str: String) -> Result<Point, Box<dyn Error + Send + Sync>> {
-    let (coord, _, _) = decode(&hash_str).map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+fn geohash_decode(hash_str: String) -> Result<Point, ErrorReport> {
+    let (coord, _, _) = decode(&hash_str).map_err(geohash_error)?;
     Ok(Point {
         x: coord.x,
         y: coord.y,
@@ -40,7 +67,7 @@ fn geohash_decode(hash_str: String) -> Result<Point, Box<dyn Error + Send + Sync
 fn geohash_neighbor(
     hash_str: String, 
     direction: i32
-) -> Result<String, Box<dyn Error + Send + Sync>> {
+) -> Result<String, ErrorReport> {
     let dir = match direction {
         0 => Direction::N,
         1 => Direction::NE,
@@ -50,16 +77,20 @@ fn geohash_neighbor(
         5 => Direction::SW,
         6 => Direction::W,
         7 => Direction::NW,
-        _ => return Err("Invalid direction. Must be 0-7 (N, NE, E, SE, S, SW, W, NW)".into()),
+        _ => {
+            return Err(InvalidValue::new("Invalid direction. Must be 0-7 (N, NE, E, SE, S, SW, W, NW)")
+                .with_detail(format!("Got {}.", direction))
+                .parameter_report())
+        }
     };
     
-    neighbor(&hash_str, dir).map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)
+    neighbor(&hash_str, dir).map_err(geohash_error)
 }
 
 /// Get all neighboring geohashes for the given geohash
 #[pg_extern]
-fn geohash_neighbors(hash_str: String) -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
-    let neighbors_result = neighbors(&hash_str).map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+fn geohash_neighbors(hash_str: String) -> Result<Vec<String>, ErrorReport> {
+    let neighbors_result = neighbors(&hash_str).map_err(geohash_error)?;
     
     Ok(vec![
         neighbors_result.n,
@@ -145,13 +176,31 @@ mod tests {
     }
 
     #[pg_test]
-    #[should_panic]
+    #[should_panic(expected = "invalid input syntax for type text: invalid geohash")]
     fn test_geohash_invalid_hash_via_spi() {
         Spi::get_one::<Point>(
             "SELECT geohash_decode('invalid_hash_123')"
         ).expect("SPI call failed");
     }
 
+    #[pg_test]
+    fn test_geohash_error_sqlstates_via_spi() {
+        Spi::run(
+            "CREATE FUNCTION pg_temp.sqlstate_of(query text) RETURNS text LANGUAGE plpgsql AS $$ \
+             BEGIN EXECUTE query; RETURN NULL; \
+             EXCEPTION WHEN OTHERS THEN RETURN SQLSTATE; END $$"
+        ).unwrap();
+
+        let sqlstate_of = |query: &str| {
+            Spi::get_one_with_args::<String>("SELECT pg_temp.sqlstate_of($1)", &[query.into()])
+                .expect("SPI result should not be NULL").unwrap()
+        };
+
+        assert_eq!(sqlstate_of("SELECT geohash_encode_with_precision(point(0.0, 0.0), 13)"), "22023");
+        assert_eq!(sqlstate_of("SELECT geohash_neighbor('ezs42', 8)"), "22023");
+        assert_eq!(sqlstate_of("SELECT geohash_decode('invalid_hash_123')"), "22P02");
+    }
+
     #[pg_test]
     #[should_panic(expected = "Precision must be between 1 and 12")]
     fn test_geohash_invalid_precision_via_spi() {
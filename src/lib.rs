@@ -1,9 +1,32 @@
+mod address_list;
+mod config;
+mod datum;
+mod error;
 mod email_addr;
+mod email_classification;
+mod email_hash;
+mod email_mask;
+mod email_pattern;
+mod email_suggest;
+mod email_typmod;
+mod email_validation;
+mod extract;
+mod mailbox;
+mod mailto;
 mod twid;
 mod geohash;
+mod public_suffix;
 
 ::pgrx::pg_module_magic!(name, version);
 
+#[pgrx::pg_guard]
+pub extern "C-unwind" fn _PG_init() {
+    config::init();
+    email_hash::init();
+    email_validation::init();
+    public_suffix::init();
+}
+
 
 /// This module is required by `cargo pgrx test` invocations.
 /// It must be visible at the root of your extension crate.
@@ -0,0 +1,717 @@
+use pgrx::prelude::*;
+use pgrx::datum::{FromDatum, IntoDatum};
+use pgrx::pg_sys::panic::ErrorReport;
+use pgrx::wrappers::rust_regtypein;
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig};
+use base64::engine::DecodePaddingMode;
+use base64::Engine;
+use encoding_rs::Encoding;
+use std::borrow::Cow;
+use std::ffi::{CStr, CString};
+use std::fmt::{self, Display};
+use std::str::FromStr;
+use crate::datum::{impl_sql_type, report_input_error, take_message_bytes};
+use crate::email_addr::EmailAddr;
+use crate::error::{raise, InvalidValue};
+
+/// An RFC 5322 mailbox: an optional display name and an address, as in
+/// `"Chen, Mei" <mei@example.tw>`.
+///
+/// The display name is stored decoded: RFC 2047 encoded-words are decoded
+/// and whitespace between words is collapsed. Output is the canonical
+/// form: the display name as atoms or a quoted string, in UTF-8
+/// (RFC 6532), followed by the address in angle brackets.
+#[derive(Debug)]
+pub(crate) struct Mailbox {
+    display_name: Option<String>,
+    address: EmailAddr,
+}
+
+/// Version byte, display name length as little-endian u16 (0 for none),
+/// display name bytes, then the address in the `emailaddr` on-disk format
+const FORMAT_V1: u8 = 1;
+const V1_HEADER_LEN: usize = 3;
+
+/// Version byte leading the binary wire format, followed by the canonical
+/// text form
+const WIRE_FORMAT_V1: u8 = 1;
+
+const INVALID_MAILBOX: &str = "invalid mailbox format";
+
+fn syntax_error(detail: &str) -> InvalidValue {
+    InvalidValue::new(INVALID_MAILBOX).with_detail(detail)
+}
+
+impl Mailbox {
+    /// Builds a mailbox, checking the display name. Surrounding whitespace,
+    /// Unicode included, is trimmed from the display name, as it would be
+    /// lost in the text form; an empty display name is the same as none.
+    pub(crate) fn new(display_name: Option<String>, address: EmailAddr) -> Result<Mailbox, InvalidValue> {
+        let display_name = display_name
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty());
+        if let Some(name) = &display_name {
+            // Tabs are white space and survive in a quoted string
+            if name.chars().any(|c| c.is_control() && c != '\t') {
+                return Err(syntax_error("The display name contains control characters."));
+            }
+            if name.len() > u16::MAX as usize {
+                return Err(syntax_error("The display name is too long."));
+            }
+        }
+        Ok(Mailbox { display_name, address })
+    }
+
+    pub(crate) fn display_name(&self) -> Option<&str> {
+        self.display_name.as_deref()
+    }
+
+    pub(crate) fn into_address(self) -> EmailAddr {
+        self.address
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let name = self.display_name.as_deref().unwrap_or_default();
+        // checked by Mailbox::new
+        let len = u16::try_from(name.len()).expect("display name too long");
+
+        let mut bytes = Vec::with_capacity(V1_HEADER_LEN + name.len() + 64);
+        bytes.push(FORMAT_V1);
+        bytes.extend_from_slice(&len.to_le_bytes());
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.extend_from_slice(&self.address.encode());
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Mailbox, &'static str> {
+        match bytes.first() {
+            Some(&FORMAT_V1) => {
+                let header = bytes.get(..V1_HEADER_LEN).ok_or("corrupt mailbox value")?;
+                let len = u16::from_le_bytes([header[1], header[2]]) as usize;
+                let name = bytes
+                    .get(V1_HEADER_LEN..V1_HEADER_LEN + len)
+                    .and_then(|name| std::str::from_utf8(name).ok())
+                    .ok_or("corrupt mailbox value")?;
+                let address = EmailAddr::decode(&bytes[V1_HEADER_LEN + len..])?;
+
+                Ok(Mailbox {
+                    display_name: (len > 0).then(|| name.to_string()),
+                    address,
+                })
+            }
+            _ => Err("unsupported mailbox storage format"),
+        }
+    }
+}
+
+/// A word of a phrase (a display name or group name)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Word {
+    Atom(String),
+    /// The content of a quoted string, unescaped
+    Quoted(String),
+}
+
+/// Characters of the atoms of a phrase: RFC 5322 `atext`, UTF-8 (RFC 6532),
+/// and the `.` obsolete phrases allow (`John Q. Public`).
+fn is_phrase_char(c: char) -> bool {
+    !c.is_whitespace() && !c.is_control() && !"()<>[]:;@\\,\"".contains(c)
+}
+
+/// Reads the parts of RFC 5322 address headers.
+pub(crate) struct Scanner<'a> {
+    input: &'a str,
+    pos: usize,
+    any_profile: bool,
+}
+
+impl<'a> Scanner<'a> {
+    pub(crate) fn new(input: &'a str) -> Scanner<'a> {
+        Scanner { input, pos: 0, any_profile: false }
+    }
+
+    /// A scanner for type input, accepting addresses valid under any
+    /// validation profile like `emailaddr` input does
+    pub(crate) fn for_type_input(input: &'a str) -> Scanner<'a> {
+        Scanner { input, pos: 0, any_profile: true }
+    }
+
+    /// The byte offset of the next character
+    pub(crate) fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub(crate) fn set_pos(&mut self, pos: usize) {
+        self.pos = pos;
+    }
+
+    pub(crate) fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    pub(crate) fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    /// Consumes `c` if it is the next character.
+    pub(crate) fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Skips whitespace, including folded line breaks, and comments.
+    pub(crate) fn skip_cfws(&mut self) -> Result<(), InvalidValue> {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() => {
+                    self.bump();
+                }
+                Some('(') => self.skip_comment()?,
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Skips a comment, which may nest, starting at its `(`.
+    fn skip_comment(&mut self) -> Result<(), InvalidValue> {
+        let mut depth = 0;
+        while let Some(c) = self.bump() {
+            match c {
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+                '\\' => {
+                    self.bump();
+                }
+                _ => {}
+            }
+        }
+        Err(syntax_error("A comment is not closed with \")\"."))
+    }
+
+    /// Reads a quoted string starting at its `"`, returning it as written,
+    /// quotes and escapes included.
+    fn raw_quoted_string(&mut self) -> Result<&'a str, InvalidValue> {
+        let start = self.pos;
+        self.bump();
+        while let Some(c) = self.bump() {
+            match c {
+                '"' => return Ok(&self.input[start..self.pos]),
+                '\\' => {
+                    self.bump();
+                }
+                _ => {}
+            }
+        }
+        Err(syntax_error("A quoted string is not closed with '\"'."))
+    }
+
+    /// Reads the words of a display name or group name, up to the first
+    /// character that can't be part of one.
+    pub(crate) fn phrase(&mut self) -> Result<Vec<Word>, InvalidValue> {
+        let mut words = Vec::new();
+        loop {
+            self.skip_cfws()?;
+            match self.peek() {
+                Some('"') => {
+                    let raw = self.raw_quoted_string()?;
+                    words.push(Word::Quoted(unquote(raw)));
+                }
+                Some(c) if is_phrase_char(c) => {
+                    let start = self.pos;
+                    while self.peek().is_some_and(is_phrase_char) {
+                        self.bump();
+                    }
+                    words.push(Word::Atom(self.input[start..self.pos].to_string()));
+                }
+                _ => return Ok(words),
+            }
+        }
+    }
+
+    /// Reads an addr-spec up to the first of `delimiters` outside a quoted
+    /// string or domain literal, dropping comments.
+    fn addr_spec(&mut self, delimiters: &[char]) -> Result<String, InvalidValue> {
+        let mut spec = String::new();
+        loop {
+            match self.peek() {
+                None => break,
+                Some(c) if delimiters.contains(&c) => break,
+                Some('"') => spec.push_str(self.raw_quoted_string()?),
+                Some('(') => self.skip_comment()?,
+                Some('[') => {
+                    let start = self.pos;
+                    while self.bump().is_some_and(|c| c != ']') {}
+                    spec.push_str(&self.input[start..self.pos]);
+                }
+                Some(c) => {
+                    self.bump();
+                    spec.push(c);
+                }
+            }
+        }
+        Ok(spec.trim().to_string())
+    }
+
+    /// Reads a mailbox, either `display name <address>` or a bare address.
+    /// A bare address ends at the first of `delimiters`.
+    pub(crate) fn mailbox(&mut self, delimiters: &[char]) -> Result<Mailbox, InvalidValue> {
+        self.skip_cfws()?;
+        let start = self.pos;
+
+        let words = self.phrase()?;
+        if self.eat('<') {
+            let spec = self.addr_spec(&['>'])?;
+            if !self.eat('>') {
+                return Err(syntax_error("The address is not closed with \">\"."));
+            }
+            self.skip_cfws()?;
+            return Mailbox::new(display_name(&words), self.address(&spec)?);
+        }
+
+        // Not a name-addr, so the words were the start of the address
+        self.pos = start;
+        let spec = self.addr_spec(delimiters)?;
+        Mailbox::new(None, self.address(&spec)?)
+    }
+
+    fn address(&self, spec: &str) -> Result<EmailAddr, InvalidValue> {
+        if self.any_profile {
+            EmailAddr::from_input(spec)
+        } else {
+            EmailAddr::from_str(spec)
+        }
+    }
+}
+
+/// The content of a quoted string written as `raw`, with its quotes and
+/// escapes removed and folded line breaks unfolded.
+fn unquote(raw: &str) -> String {
+    let mut content = String::new();
+    let mut chars = raw[1..raw.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => content.extend(chars.next()),
+            '\r' | '\n' => {}
+            c => content.push(c),
+        }
+    }
+    content
+}
+
+/// Base64 as used in encoded-words, tolerating missing padding
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &base64::alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Decodes an RFC 2047 encoded-word such as `=?UTF-8?B?6Zmz576O?=`.
+/// Returns `None` for anything that isn't one, or uses a charset or
+/// encoding this doesn't know.
+fn decode_encoded_word(word: &str) -> Option<String> {
+    let inner = word.strip_prefix("=?")?.strip_suffix("?=")?;
+    let mut parts = inner.split('?');
+    let (charset, encoding, text) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() {
+        return None;
+    }
+
+    // RFC 2231 allows a language after the charset: `UTF-8*en`
+    let charset = charset.split('*').next()?;
+    let charset = Encoding::for_label(charset.as_bytes())?;
+    let bytes = match encoding {
+        "B" | "b" => BASE64.decode(text).ok()?,
+        "Q" | "q" => decode_q(text)?,
+        _ => return None,
+    };
+
+    let (decoded, _, had_errors) = charset.decode(&bytes);
+    (!had_errors).then(|| decoded.into_owned())
+}
+
+/// Decodes the `Q` encoding: `_` is a space, `=XX` a byte in hex.
+fn decode_q(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut input = text.bytes();
+    while let Some(b) = input.next() {
+        match b {
+            b'_' => bytes.push(b' '),
+            b'=' => {
+                let hex = [input.next()?, input.next()?];
+                bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            b => bytes.push(b),
+        }
+    }
+    Some(bytes)
+}
+
+/// Joins the words of a phrase into a display name, decoding encoded-words
+/// in atoms. The space between two encoded-words is dropped (RFC 2047,
+/// section 6.2).
+pub(crate) fn display_name(words: &[Word]) -> Option<String> {
+    let mut name = String::new();
+    let mut previous_encoded = false;
+    for word in words {
+        let (text, encoded) = match word {
+            Word::Atom(atom) => match decode_encoded_word(atom) {
+                Some(decoded) => (Cow::Owned(decoded), true),
+                None => (Cow::Borrowed(atom.as_str()), false),
+            },
+            // Encoded-words inside quoted strings are not decoded
+            Word::Quoted(content) => (Cow::Borrowed(content.as_str()), false),
+        };
+
+        let adjacent_encoded = encoded && previous_encoded;
+        if !name.is_empty() && !adjacent_encoded {
+            name.push(' ');
+        }
+        name.push_str(&text);
+        previous_encoded = encoded;
+    }
+
+    let name = name.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// RFC 5322 `atext`, plus UTF-8 (RFC 6532)
+fn is_atext(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-/=?^_`{|}~".contains(c) || (!c.is_ascii() && !c.is_whitespace())
+}
+
+/// The display name as written in a header: as atoms if it can be,
+/// otherwise as a quoted string.
+fn quote_phrase(name: &str) -> Cow<'_, str> {
+    let atoms = name.split(' ').all(|word| {
+        // A word that looks like an encoded-word would be decoded when read back
+        !word.is_empty() && word.chars().all(is_atext) && !word.starts_with("=?")
+    });
+    if atoms {
+        return Cow::Borrowed(name);
+    }
+
+    let mut quoted = String::with_capacity(name.len() + 2);
+    quoted.push('"');
+    for c in name.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    Cow::Owned(quoted)
+}
+
+impl FromStr for Mailbox {
+    type Err = InvalidValue;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut scanner = Scanner::for_type_input(s);
+        let mailbox = scanner.mailbox(&[])?;
+        if scanner.peek().is_some() {
+            return Err(syntax_error("Unexpected text after the address."));
+        }
+        Ok(mailbox)
+    }
+}
+
+impl Display for Mailbox {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.display_name {
+            Some(name) => write!(f, "{} <{}>", quote_phrase(name), self.address),
+            None => write!(f, "{}", self.address),
+        }
+    }
+}
+
+impl FromDatum for Mailbox {
+    unsafe fn from_polymorphic_datum(datum: pg_sys::Datum, is_null: bool, typoid: pg_sys::Oid) -> Option<Self> {
+        let bytes = <&[u8]>::from_polymorphic_datum(datum, is_null, typoid)?;
+
+        Some(Mailbox::decode(bytes).unwrap_or_else(|e| {
+            ereport!(ERROR, PgSqlErrorCode::ERRCODE_DATA_CORRUPTED, e);
+        }))
+    }
+}
+
+impl IntoDatum for Mailbox {
+    fn into_datum(self) -> Option<pg_sys::Datum> {
+        self.encode().into_datum()
+    }
+
+    fn type_oid() -> pg_sys::Oid {
+        rust_regtypein::<Self>()
+    }
+}
+
+impl_sql_type!(Mailbox, "mailbox");
+
+extension_sql!(
+    r#"
+CREATE TYPE mailbox;
+
+CREATE FUNCTION mailbox_in(cstring) RETURNS mailbox
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'mailbox_in_wrapper';
+
+CREATE FUNCTION mailbox_out(mailbox) RETURNS cstring
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'mailbox_out_wrapper';
+
+CREATE FUNCTION mailbox_recv(internal) RETURNS mailbox
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'mailbox_recv_wrapper';
+
+CREATE FUNCTION mailbox_send(mailbox) RETURNS bytea
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'mailbox_send_wrapper';
+
+CREATE TYPE mailbox (
+    INTERNALLENGTH = variable,
+    INPUT = mailbox_in,
+    OUTPUT = mailbox_out,
+    RECEIVE = mailbox_recv,
+    SEND = mailbox_send,
+    STORAGE = extended
+);
+"#,
+    name = "mailbox_type",
+    creates = [Type(Mailbox)],
+    requires = ["emailaddr_type"],
+);
+
+#[pg_extern(immutable, parallel_safe, sql = false)]
+fn mailbox_in(input: &CStr, fcinfo: pg_sys::FunctionCallInfo) -> Option<Mailbox> {
+    let parsed = match input.to_str() {
+        Ok(input_str) => Mailbox::from_str(input_str),
+        Err(e) => Err(InvalidValue::new("invalid UTF-8 in mailbox input").with_detail(e.to_string())),
+    };
+
+    match parsed {
+        Ok(mailbox) => Some(mailbox),
+        Err(e) => {
+            report_input_error(fcinfo, "mailbox", e);
+            None
+        }
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, sql = false)]
+fn mailbox_out(input: Mailbox) -> CString {
+    // display names and addresses never contain NUL
+    CString::new(input.to_string()).unwrap()
+}
+
+#[pg_extern(immutable, parallel_safe, sql = false)]
+fn mailbox_recv(mut internal: Internal) -> Mailbox {
+    let buf = unsafe { internal.get_mut::<pg_sys::StringInfoData>() }.unwrap();
+    let bytes = unsafe { take_message_bytes(buf) };
+
+    let parsed = match bytes.split_first() {
+        Some((&WIRE_FORMAT_V1, text)) => std::str::from_utf8(text)
+            .map_err(|e| InvalidValue::new("invalid UTF-8 in mailbox input").with_detail(e.to_string()))
+            .and_then(Mailbox::from_str),
+        Some((version, _)) => Err(InvalidValue::new("unsupported mailbox binary format version")
+            .with_detail(format!("Got version {}, only version {} is supported.", version, WIRE_FORMAT_V1))),
+        None => Err(InvalidValue::new("missing mailbox binary format version")),
+    };
+    parsed.unwrap_or_else(|e| raise(e.binary_input_report("mailbox")))
+}
+
+/// The binary wire format: the version byte `1` followed by the canonical
+/// text form as UTF-8
+#[pg_extern(immutable, parallel_safe, sql = false)]
+fn mailbox_send(input: Mailbox) -> Vec<u8> {
+    let text = input.to_string();
+    let mut bytes = Vec::with_capacity(1 + text.len());
+    bytes.push(WIRE_FORMAT_V1);
+    bytes.extend_from_slice(text.as_bytes());
+    bytes
+}
+
+/// Build a mailbox from an address and an optional display name. NULL if
+/// the address is NULL.
+#[pg_extern(immutable, parallel_safe)]
+fn mailbox(
+    address: Option<EmailAddr>,
+    display_name: default!(Option<&str>, "NULL"),
+) -> Result<Option<Mailbox>, ErrorReport> {
+    let Some(address) = address else {
+        return Ok(None);
+    };
+    Mailbox::new(display_name.map(str::to_string), address)
+        .map(Some)
+        .map_err(InvalidValue::parameter_report)
+}
+
+/// The display name, decoded, or NULL if there is none
+#[pg_extern(immutable, parallel_safe)]
+fn mailbox_display_name(input: Mailbox) -> Option<String> {
+    input.display_name().map(str::to_string)
+}
+
+/// The address of the mailbox
+#[pg_extern(immutable, parallel_safe)]
+fn mailbox_address(input: Mailbox) -> EmailAddr {
+    input.into_address()
+}
+
+/// Cast mailbox to EmailAddr, dropping the display name
+#[pg_cast(assignment)]
+fn cast_mailbox_to_emailaddr(input: Mailbox) -> EmailAddr {
+    input.into_address()
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encoded_words() {
+        assert_eq!(decode_encoded_word("=?UTF-8?B?6Zmz576O?=").as_deref(), Some("陳美"));
+        assert_eq!(decode_encoded_word("=?utf-8?b?6Zmz576O?=").as_deref(), Some("陳美"));
+        assert_eq!(decode_encoded_word("=?ISO-8859-1?Q?Andr=E9_P?=").as_deref(), Some("André P"));
+        assert_eq!(decode_encoded_word("=?Big5?B?s6+s/A==?=").as_deref(), Some("陳美"));
+        assert_eq!(decode_encoded_word("=?UTF-8*zh?Q?abc?=").as_deref(), Some("abc"));
+
+        assert_eq!(decode_encoded_word("=?x-unknown?Q?abc?="), None);
+        assert_eq!(decode_encoded_word("=?UTF-8?X?abc?="), None);
+        assert_eq!(decode_encoded_word("=?UTF-8?Q?a=Z1?="), None);
+        assert_eq!(decode_encoded_word("plain"), None);
+    }
+
+    #[test]
+    fn test_display_name() {
+        let atom = |s: &str| Word::Atom(s.to_string());
+        let quoted = |s: &str| Word::Quoted(s.to_string());
+
+        assert_eq!(display_name(&[atom("Mei"), atom("Chen")]).as_deref(), Some("Mei Chen"));
+        assert_eq!(display_name(&[quoted("Chen, Mei")]).as_deref(), Some("Chen, Mei"));
+        assert_eq!(
+            display_name(&[atom("=?UTF-8?Q?a?="), atom("=?UTF-8?Q?b?="), atom("c")]).as_deref(),
+            Some("ab c")
+        );
+        assert_eq!(display_name(&[quoted("=?UTF-8?Q?a?=")]).as_deref(), Some("=?UTF-8?Q?a?="));
+        assert_eq!(display_name(&[quoted("")]), None);
+        assert_eq!(display_name(&[]), None);
+    }
+
+    #[test]
+    fn test_quote_phrase() {
+        assert_eq!(quote_phrase("Mei Chen"), "Mei Chen");
+        assert_eq!(quote_phrase("陳美"), "陳美");
+        assert_eq!(quote_phrase("Chen, Mei"), "\"Chen, Mei\"");
+        assert_eq!(quote_phrase("John Q. Public"), "\"John Q. Public\"");
+        assert_eq!(quote_phrase("Mei  Chen"), "\"Mei  Chen\"");
+        assert_eq!(quote_phrase(r#"John "JD" Doe"#), r#""John \"JD\" Doe""#);
+        assert_eq!(quote_phrase("=?UTF-8?Q?a?="), "\"=?UTF-8?Q?a?=\"");
+    }
+
+    #[pg_test]
+    fn test_mailbox_parsing() {
+        let canonical = |s: &str| Mailbox::from_str(s).map(|m| m.to_string());
+
+        assert_eq!(canonical(r#""Chen, Mei" <mei@example.tw>"#).unwrap(), r#""Chen, Mei" <mei@example.tw>"#);
+        assert_eq!(canonical("Mei Chen <mei@example.tw>").unwrap(), "Mei Chen <mei@example.tw>");
+        assert_eq!(canonical("  Mei \r\n Chen (work)  <mei@example.tw> ").unwrap(), "Mei Chen <mei@example.tw>");
+        assert_eq!(canonical("mei@example.tw").unwrap(), "mei@example.tw");
+        assert_eq!(canonical("<mei@example.tw>").unwrap(), "mei@example.tw");
+        assert_eq!(canonical("mei@example.tw (Mei Chen)").unwrap(), "mei@example.tw");
+        assert_eq!(canonical("=?UTF-8?B?6Zmz576O?= <mei@example.tw>").unwrap(), "陳美 <mei@example.tw>");
+        assert_eq!(
+            canonical("=?ISO-8859-1?Q?Andr=E9?= Pirard <pirard@example.be>").unwrap(),
+            "André Pirard <pirard@example.be>"
+        );
+        assert_eq!(canonical("John Q. Public <jqp@example.com>").unwrap(), r#""John Q. Public" <jqp@example.com>"#);
+        assert_eq!(canonical(r#""John \"JD\" Doe" <jd@example.com>"#).unwrap(), r#""John \"JD\" Doe" <jd@example.com>"#);
+        // Type input accepts addresses stored under another validation profile
+        assert_eq!(canonical(r#"Doe <"john doe"@example.com>"#).unwrap(), r#"Doe <"john doe"@example.com>"#);
+
+        for invalid in [
+            r#""Mei <mei@example.tw>"#,
+            "Mei <mei@example.tw",
+            "Mei <not-an-email>",
+            "Mei <mei@example.tw> extra",
+            "Mei (comment <mei@example.tw>",
+            "Mei Chen mei@example.tw",
+            "",
+            "\"Mei\u{1}\" <mei@example.tw>",
+        ] {
+            assert!(Mailbox::from_str(invalid).is_err(), "{}", invalid);
+        }
+
+        // Tabs are the only control characters allowed in a display name
+        let tabbed = Mailbox::from_str("\"Mei\tChen\" <mei@example.tw>").unwrap();
+        assert_eq!(tabbed.display_name(), Some("Mei\tChen"));
+    }
+
+    #[pg_test]
+    fn test_mailbox_encoding() {
+        for s in [r#""Chen, Mei" <mei@example.tw>"#, "mei@example.tw", "陳美 <user@bücher.de>"] {
+            let mailbox = Mailbox::from_str(s).unwrap();
+            let decoded = Mailbox::decode(&mailbox.encode()).unwrap();
+            assert_eq!(decoded.to_string(), mailbox.to_string());
+            assert_eq!(decoded.display_name(), mailbox.display_name());
+        }
+        assert!(Mailbox::decode(&[9]).is_err());
+    }
+
+    #[pg_test]
+    fn test_mailbox_functions() {
+        let name = Spi::get_one::<String>(
+            r#"SELECT mailbox_display_name('"Chen, Mei" <mei@example.tw>'::mailbox)"#
+        ).unwrap();
+        assert_eq!(name.as_deref(), Some("Chen, Mei"));
+
+        let name = Spi::get_one::<String>("SELECT mailbox_display_name('mei@example.tw'::mailbox)").unwrap();
+        assert_eq!(name, None);
+
+        let address = Spi::get_one::<String>(
+            r#"SELECT mailbox_address('"Chen, Mei" <mei@example.tw>')::text"#
+        ).unwrap();
+        assert_eq!(address.as_deref(), Some("mei@example.tw"));
+
+        let same = Spi::get_one::<bool>(
+            "SELECT 'Mei Chen <mei@EXAMPLE.tw>'::mailbox::emailaddr = 'mei@example.tw'::emailaddr"
+        ).unwrap();
+        assert_eq!(same, Some(true));
+
+        let built = Spi::get_one::<String>("SELECT mailbox('mei@example.tw', 'Chen, Mei')::text").unwrap();
+        assert_eq!(built.as_deref(), Some(r#""Chen, Mei" <mei@example.tw>"#));
+
+        let built = Spi::get_one::<String>("SELECT mailbox(NULL, 'Chen, Mei')::text").unwrap();
+        assert_eq!(built, None);
+        let built = Spi::get_one::<String>("SELECT mailbox('mei@example.tw', NULL)::text").unwrap();
+        assert_eq!(built.as_deref(), Some("mei@example.tw"));
+    }
+
+    #[pg_test]
+    fn test_mailbox_round_trip() {
+        for (name, expected) in [
+            (" Mei Chen ", "Mei Chen <mei@example.tw>|Mei Chen"),
+            ("\u{3000}陳美\u{a0}", "陳美 <mei@example.tw>|陳美"),
+            ("\tChen, Mei ", "\"Chen, Mei\" <mei@example.tw>|Chen, Mei"),
+            ("Mei\tChen", "\"Mei\tChen\" <mei@example.tw>|Mei\tChen"),
+            ("   ", "mei@example.tw|"),
+        ] {
+            // The text form parses back to the same mailbox
+            let row = Spi::get_one_with_args::<String>(
+                "SELECT format('%s|%s', m, mailbox_display_name(m)) \
+                 FROM (SELECT mailbox('mei@example.tw', $1)::text::mailbox) AS t(m)",
+                &[name.into()],
+            ).unwrap();
+            assert_eq!(row.as_deref(), Some(expected), "{:?}", name);
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use pgrx::prelude::*;
+use pgrx::pg_sys::panic::ErrorReport;
+use pgrx::JsonB;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde_json::{Map, Value};
+use std::str::FromStr;
+use crate::email_addr::EmailAddr;
+use crate::error::{raise, InvalidValue};
+
+const SCHEME: &str = "mailto:";
+
+/// Characters a header field name or value may hold unencoded: RFC 6068
+/// `qchar`, that is RFC 3986 unreserved characters and `some-delims`.
+/// `+` is encoded too, as some clients read it as a space.
+const QCHAR: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-').remove(b'.').remove(b'_').remove(b'~')
+    .remove(b'!').remove(b'$').remove(b'\'').remove(b'(').remove(b')').remove(b'*')
+    .remove(b',').remove(b';').remove(b':').remove(b'@');
+
+/// Characters an address in the target list may hold unencoded: `qchar`
+/// with `+`, but without the `,` separating addresses.
+const ADDRESS: &AsciiSet = &QCHAR.remove(b'+').add(b',');
+
+const INVALID_MAILTO: &str = "invalid mailto URI";
+
+/// The parts of a `mailto:` URI, percent-decoded
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Mailto {
+    /// The addresses of the target list and of `to` header fields
+    addresses: Vec<String>,
+    /// Header fields with lowercase names, in order
+    headers: Vec<(String, String)>,
+}
+
+/// Percent-decodes `s`, which must decode to UTF-8.
+fn decode(s: &str) -> Result<String, InvalidValue> {
+    let bytes = s.as_bytes();
+    for (i, _) in s.match_indices('%') {
+        let escape = bytes.get(i + 1..i + 3);
+        if !escape.is_some_and(|hex| hex.iter().all(u8::is_ascii_hexdigit)) {
+            return Err(InvalidValue::new(INVALID_MAILTO)
+                .with_detail(format!("\"{}\" has a \"%\" not followed by two hex digits.", s)));
+        }
+    }
+
+    percent_decode_str(s)
+        .decode_utf8()
+        .map(|decoded| decoded.into_owned())
+        .map_err(|_| InvalidValue::new(INVALID_MAILTO).with_detail(format!("\"{}\" does not decode to UTF-8.", s)))
+}
+
+/// Decodes the comma-separated addresses of `list` into `addresses`.
+fn decode_addresses(list: &str, addresses: &mut Vec<String>) -> Result<(), InvalidValue> {
+    for address in list.split(',') {
+        let address = decode(address)?;
+        let address = address.trim();
+        if !address.is_empty() {
+            addresses.push(address.to_string());
+        }
+    }
+    Ok(())
+}
+
+impl FromStr for Mailto {
+    type Err = InvalidValue;
+
+    /// Parses a `mailto:` URI (RFC 6068). Addresses are not validated here.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let rest = match s.get(..SCHEME.len()) {
+            Some(scheme) if scheme.eq_ignore_ascii_case(SCHEME) => &s[SCHEME.len()..],
+            _ => {
+                return Err(InvalidValue::new(INVALID_MAILTO)
+                    .with_detail("The URI does not start with \"mailto:\"."))
+            }
+        };
+        // A fragment identifies nothing within a message
+        let rest = rest.split_once('#').map_or(rest, |(rest, _)| rest);
+        let (to, query) = rest.split_once('?').unwrap_or((rest, ""));
+
+        let mut mailto = Mailto::default();
+        decode_addresses(to, &mut mailto.addresses)?;
+        for field in query.split('&').filter(|field| !field.is_empty()) {
+            let (name, value) = field.split_once('=').unwrap_or((field, ""));
+            let name = decode(name)?.to_ascii_lowercase();
+            if name == "to" {
+                decode_addresses(value, &mut mailto.addresses)?;
+            } else {
+                mailto.headers.push((name, decode(value)?));
+            }
+        }
+        Ok(mailto)
+    }
+}
+
+impl Mailto {
+    /// The URI for these parts, percent-encoding everything that must be.
+    fn to_uri(&self) -> String {
+        let mut uri = String::from(SCHEME);
+        for (i, address) in self.addresses.iter().enumerate() {
+            if i > 0 {
+                uri.push(',');
+            }
+            uri.extend(utf8_percent_encode(address, ADDRESS));
+        }
+        for (i, (name, value)) in self.headers.iter().enumerate() {
+            uri.push(if i == 0 { '?' } else { '&' });
+            uri.extend(utf8_percent_encode(name, QCHAR));
+            uri.push('=');
+            uri.extend(utf8_percent_encode(value, QCHAR));
+        }
+        uri
+    }
+
+    /// The header fields as a JSON object. The address lists `cc` and
+    /// `bcc` given more than once have their values joined with `, `; of
+    /// any other field given more than once, the first value is kept.
+    fn headers_json(&self) -> Value {
+        let mut headers = Map::new();
+        for (name, value) in &self.headers {
+            match headers.get_mut(name) {
+                Some(Value::String(existing)) if matches!(name.as_str(), "cc" | "bcc") => {
+                    existing.push_str(", ");
+                    existing.push_str(value);
+                }
+                Some(_) => {}
+                None => {
+                    headers.insert(name.clone(), Value::String(value.clone()));
+                }
+            }
+        }
+        Value::Object(headers)
+    }
+}
+
+extension_sql!(
+    r#"
+CREATE TYPE mailto_parts AS (
+    addresses emailaddr[],
+    headers jsonb
+);
+"#,
+    name = "mailto_parts",
+    requires = ["emailaddr_type"],
+);
+
+/// Splits a `mailto:` URI into its target addresses, including those of
+/// `to` fields, and its other header fields as a JSON object with
+/// lowercase keys, all percent-decoded. The addresses are validated like
+/// `emailaddr` input.
+#[pg_extern(stable, parallel_safe, requires = ["mailto_parts"])]
+fn mailto_parse(uri: &str) -> Result<pgrx::composite_type!('static, "mailto_parts"), ErrorReport> {
+    let mailto = Mailto::from_str(uri).map_err(InvalidValue::parameter_report)?;
+    let addresses = mailto
+        .addresses
+        .iter()
+        .map(|address| EmailAddr::from_str(address).map_err(|e| e.input_report("emailaddr")))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut result = PgHeapTuple::new_composite_type("mailto_parts").unwrap();
+    result.set_by_name("addresses", addresses).unwrap();
+    result.set_by_name("headers", JsonB(mailto.headers_json())).unwrap();
+    Ok(result)
+}
+
+/// Builds a `mailto:` URI from target addresses and a JSON object of
+/// header fields such as `subject`, `cc` and `body`, percent-encoding
+/// them. NULL if the addresses are NULL.
+#[pg_extern(immutable, parallel_safe)]
+fn mailto_build(
+    addresses: Option<Vec<Option<EmailAddr>>>,
+    headers: default!(Option<JsonB>, "NULL"),
+) -> Result<Option<String>, ErrorReport> {
+    let Some(addresses) = addresses else {
+        return Ok(None);
+    };
+    let addresses = addresses
+        .into_iter()
+        .map(|address| match address {
+            Some(address) => Ok(address.to_string()),
+            None => Err(InvalidValue::new("mailto addresses cannot be NULL").parameter_report()),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let headers = match headers.map(|headers| headers.0) {
+        None | Some(Value::Null) => Vec::new(),
+        Some(Value::Object(fields)) => fields
+            .into_iter()
+            .map(|(name, value)| match value {
+                Value::String(value) => Ok((name, value)),
+                _ => Err(InvalidValue::new("invalid mailto header")
+                    .with_detail(format!("The value of \"{}\" is not a string.", name))
+                    .parameter_report()),
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        Some(_) => raise(
+            InvalidValue::new("invalid mailto header")
+                .with_detail("The headers must be a JSON object.")
+                .parameter_report(),
+        ),
+    };
+
+    Ok(Some(Mailto { addresses, headers }.to_uri()))
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+
+    fn mailto(addresses: &[&str], headers: &[(&str, &str)]) -> Mailto {
+        Mailto {
+            addresses: addresses.iter().map(|a| a.to_string()).collect(),
+            headers: headers.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect(),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Mailto::from_str("mailto:jane@example.com?subject=Hello%20there&cc=bob@example.com").unwrap(),
+            mailto(&["jane@example.com"], &[("subject", "Hello there"), ("cc", "bob@example.com")])
+        );
+        assert_eq!(
+            Mailto::from_str("MAILTO:a@example.com,b@example.com?To=c@example.com&body=1+1%0D%0A").unwrap(),
+            mailto(&["a@example.com", "b@example.com", "c@example.com"], &[("body", "1+1\r\n")])
+        );
+        assert_eq!(
+            Mailto::from_str("mailto:%22not%40me%22@example.org?subject=%E9%99%B3%E7%BE%8E").unwrap(),
+            mailto(&["\"not@me\"@example.org"], &[("subject", "陳美")])
+        );
+        assert_eq!(Mailto::from_str("mailto:?to=&subject").unwrap(), mailto(&[], &[("subject", "")]));
+        assert_eq!(
+            Mailto::from_str("mailto:a@example.com?subject=Hi#section-2").unwrap(),
+            mailto(&["a@example.com"], &[("subject", "Hi")])
+        );
+        assert_eq!(Mailto::from_str("mailto:a@example.com#top").unwrap(), mailto(&["a@example.com"], &[]));
+
+        assert!(Mailto::from_str("http://example.com").is_err());
+        assert!(Mailto::from_str("mailto:a@example.com?subject=100%").is_err());
+        assert!(Mailto::from_str("mailto:a@example.com?subject=%FF").is_err());
+    }
+
+    #[test]
+    fn test_to_uri() {
+        assert_eq!(
+            mailto(&["a+tag@example.com", "b@example.com"], &[("subject", "Hi & bye"), ("body", "1+1=2\r\n")]).to_uri(),
+            "mailto:a+tag@example.com,b@example.com?subject=Hi%20%26%20bye&body=1%2B1%3D2%0D%0A"
+        );
+        assert_eq!(mailto(&["\"a,b\"@example.com"], &[]).to_uri(), "mailto:%22a%2Cb%22@example.com");
+        assert_eq!(mailto(&[], &[("subject", "陳美")]).to_uri(), "mailto:?subject=%E9%99%B3%E7%BE%8E");
+
+        let parts = mailto(&["\"a,b\"@example.com"], &[("subject", "a=b&c?d%"), ("cc", "x@example.com, y@example.com")]);
+        assert_eq!(Mailto::from_str(&parts.to_uri()).unwrap(), parts);
+    }
+
+    #[test]
+    fn test_headers_json() {
+        let parts = mailto(
+            &[],
+            &[
+                ("cc", "a@example.com"),
+                ("subject", "Hi"),
+                ("bcc", "c@example.com"),
+                ("cc", "b@example.com"),
+                ("subject", "Hello"),
+                ("bcc", "d@example.com"),
+            ],
+        );
+        assert_eq!(
+            parts.headers_json(),
+            serde_json::json!({"cc": "a@example.com, b@example.com", "bcc": "c@example.com, d@example.com", "subject": "Hi"})
+        );
+    }
+
+    #[pg_test]
+    fn test_mailto_functions() {
+        let parsed = Spi::get_one::<String>(
+            "SELECT format('%s|%s', addresses, headers) \
+             FROM mailto_parse('mailto:jane@Example.com?subject=Hello%20there&cc=bob@example.com')"
+        ).unwrap();
+        assert_eq!(
+            parsed.as_deref(),
+            Some(r#"{jane@Example.com}|{"cc": "bob@example.com", "subject": "Hello there"}"#)
+        );
+
+        let built = Spi::get_one::<String>(
+            r#"SELECT mailto_build(ARRAY['jane@example.com']::emailaddr[], '{"subject": "Hello there"}')"#
+        ).unwrap();
+        assert_eq!(built.as_deref(), Some("mailto:jane@example.com?subject=Hello%20there"));
+
+        let built = Spi::get_one::<String>("SELECT mailto_build(NULL::emailaddr[])").unwrap();
+        assert_eq!(built, None);
+
+        Spi::run(
+            "CREATE FUNCTION pg_temp.sqlstate_of(query text) RETURNS text LANGUAGE plpgsql AS $$ \
+             BEGIN EXECUTE query; RETURN NULL; \
+             EXCEPTION WHEN OTHERS THEN RETURN SQLSTATE; END $$"
+        ).unwrap();
+        let sqlstate_of = |query: &str| {
+            Spi::get_one_with_args::<String>("SELECT pg_temp.sqlstate_of($1)", &[query.into()]).unwrap()
+        };
+        assert_eq!(sqlstate_of("SELECT mailto_parse('mailto:not-an-address')").as_deref(), Some("22P02"));
+        assert_eq!(sqlstate_of("SELECT mailto_parse('https://example.com')").as_deref(), Some("22023"));
+        assert_eq!(
+            sqlstate_of(r#"SELECT mailto_build('{}', '{"subject": 1}')"#).as_deref(),
+            Some("22023")
+        );
+    }
+}
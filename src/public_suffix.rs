@@ -0,0 +1,248 @@
+use pgrx::prelude::*;
+use pgrx::pg_sys::panic::ErrorReport;
+use pgrx::{GucContext, GucFlags, GucRegistry, GucSetting};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::ffi::CString;
+use std::rc::Rc;
+use crate::error::raise;
+
+/// The Public Suffix List compiled into the extension
+/// (<https://publicsuffix.org/list/public_suffix_list.dat>)
+const EMBEDDED_LIST: &str = include_str!("../data/public_suffix_list.dat");
+
+/// Path of a newer Public Suffix List to use instead of the embedded one.
+/// Empty or unset means the embedded list.
+static LIST_FILE: GucSetting<Option<CString>> = GucSetting::<Option<CString>>::new(None);
+
+pub(crate) fn init() {
+    GucRegistry::define_string_guc(
+        c"pgdatatypes_plus.public_suffix_list",
+        c"Path of a Public Suffix List file to use instead of the built-in list.",
+        c"The file is read once per session, the first time it is needed. Leave empty to use the list compiled into the extension.",
+        &LIST_FILE,
+        GucContext::Suset,
+        GucFlags::default(),
+    );
+}
+
+/// The rules of a Public Suffix List, in lowercase.
+#[derive(Debug, Default)]
+pub(crate) struct SuffixList {
+    /// `example.com`
+    normal: HashSet<String>,
+    /// `*.example.com`, stored without the `*.`
+    wildcard: HashSet<String>,
+    /// `!www.example.com`, stored without the `!`
+    exception: HashSet<String>,
+}
+
+impl SuffixList {
+    /// Parses a list in the publicsuffix.org format: one rule per line,
+    /// `//` comments, and only the first word of a line counts.
+    pub(crate) fn parse(text: &str) -> SuffixList {
+        let mut list = SuffixList::default();
+        for line in text.lines() {
+            let Some(rule) = line.split_whitespace().next() else {
+                continue;
+            };
+            if rule.starts_with("//") {
+                continue;
+            }
+
+            let rule = rule.to_lowercase();
+            if let Some(rule) = rule.strip_prefix('!') {
+                list.exception.insert(rule.to_string());
+            } else if let Some(rule) = rule.strip_prefix("*.") {
+                list.wildcard.insert(rule.to_string());
+            } else {
+                list.normal.insert(rule);
+            }
+        }
+        list
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.normal.len() + self.wildcard.len() + self.exception.len()
+    }
+
+    /// Whether the lowercase top-level domain `tld` is on the list.
+    pub(crate) fn has_tld(&self, tld: &str) -> bool {
+        self.normal.contains(tld) || self.wildcard.contains(tld)
+    }
+
+    /// The public suffix of a lowercase `domain`, following the algorithm
+    /// at <https://publicsuffix.org/list/>: exception rules win, otherwise
+    /// the longest matching rule, otherwise the last label.
+    pub(crate) fn public_suffix<'a>(&self, domain: &'a str) -> &'a str {
+        // Byte offsets of every label, longest candidate suffix first
+        let starts: Vec<usize> = std::iter::once(0)
+            .chain(domain.match_indices('.').map(|(i, _)| i + 1))
+            .collect();
+
+        for (n, &start) in starts.iter().enumerate() {
+            if self.exception.contains(&domain[start..]) {
+                // An exception rule's suffix drops its leftmost label
+                return starts.get(n + 1).map_or(domain, |&next| &domain[next..]);
+            }
+        }
+
+        for (n, &start) in starts.iter().enumerate() {
+            let suffix = &domain[start..];
+            if self.normal.contains(suffix) {
+                return suffix;
+            }
+            if let Some(&next) = starts.get(n + 1) {
+                if self.wildcard.contains(&domain[next..]) {
+                    return suffix;
+                }
+            }
+        }
+
+        &domain[*starts.last().unwrap()..]
+    }
+
+    /// The public suffix of a lowercase `domain` plus one more label, or
+    /// `None` when the domain is itself a public suffix.
+    pub(crate) fn registrable_domain<'a>(&self, domain: &'a str) -> Option<&'a str> {
+        let suffix = self.public_suffix(domain);
+        let rest = domain.strip_suffix(suffix)?.strip_suffix('.')?;
+        let start = rest.rfind('.').map_or(0, |i| i + 1);
+        Some(&domain[start..])
+    }
+}
+
+fn embedded_list() -> Rc<SuffixList> {
+    thread_local! {
+        // Parsed on first use
+        static EMBEDDED: Rc<SuffixList> = Rc::new(SuffixList::parse(EMBEDDED_LIST));
+    }
+    EMBEDDED.with(Rc::clone)
+}
+
+/// The list in effect for this session: the file named by
+/// `pgdatatypes_plus.public_suffix_list`, or the embedded list.
+pub(crate) fn current_list() -> Rc<SuffixList> {
+    thread_local! {
+        /// The last file read, by path
+        static LOADED: RefCell<Option<(CString, Rc<SuffixList>)>> = const { RefCell::new(None) };
+    }
+
+    let path = match LIST_FILE.get() {
+        Some(path) if !path.as_bytes().is_empty() => path,
+        _ => return embedded_list(),
+    };
+
+    LOADED.with(|loaded| {
+        let mut loaded = loaded.borrow_mut();
+        if let Some((loaded_path, list)) = loaded.as_ref() {
+            if *loaded_path == path {
+                return list.clone();
+            }
+        }
+
+        let list = Rc::new(read_list_file(&path.to_string_lossy()));
+        *loaded = Some((path, list.clone()));
+        list
+    })
+}
+
+fn read_list_file(path: &str) -> SuffixList {
+    let fail = |code, message: String| -> ! {
+        raise(
+            ErrorReport::new(code, message, pgrx::function_name!())
+                .set_hint("Check the pgdatatypes_plus.public_suffix_list setting."),
+        )
+    };
+
+    let text = std::fs::read_to_string(path).unwrap_or_else(|e| {
+        fail(
+            PgSqlErrorCode::ERRCODE_UNDEFINED_FILE,
+            format!("could not read public suffix list \"{}\": {}", path, e),
+        )
+    });
+
+    let list = SuffixList::parse(&text);
+    if list.len() == 0 {
+        fail(
+            PgSqlErrorCode::ERRCODE_CONFIG_FILE_ERROR,
+            format!("public suffix list \"{}\" contains no rules", path),
+        );
+    }
+    list
+}
+
+/// The number of rules in the Public Suffix List in effect for this session
+#[pg_extern(stable, parallel_safe)]
+fn public_suffix_list_size() -> i64 {
+    current_list().len() as i64
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+
+    const LIST: &str = "\
+// comment
+com
+uk
+co.uk
+*.ck
+!www.ck
+github.io   trailing words are ignored
+";
+
+    #[test]
+    fn test_public_suffix_rules() {
+        let list = SuffixList::parse(LIST);
+        assert_eq!(list.len(), 6);
+
+        assert_eq!(list.public_suffix("example.com"), "com");
+        assert_eq!(list.public_suffix("mail.corp.example.co.uk"), "co.uk");
+        assert_eq!(list.public_suffix("co.uk"), "co.uk");
+        // Wildcard and exception rules
+        assert_eq!(list.public_suffix("www.example.ck"), "example.ck");
+        assert_eq!(list.public_suffix("www.ck"), "ck");
+        // Unlisted TLDs fall back to the last label
+        assert_eq!(list.public_suffix("example.internal"), "internal");
+        assert_eq!(list.public_suffix("user.github.io"), "github.io");
+
+        assert!(list.has_tld("ck"));
+        assert!(!list.has_tld("internal"));
+    }
+
+    #[test]
+    fn test_registrable_domain() {
+        let list = SuffixList::parse(LIST);
+
+        assert_eq!(list.registrable_domain("mail.corp.example.co.uk"), Some("example.co.uk"));
+        assert_eq!(list.registrable_domain("example.com"), Some("example.com"));
+        assert_eq!(list.registrable_domain("a.b.www.ck"), Some("www.ck"));
+        assert_eq!(list.registrable_domain("co.uk"), None);
+        assert_eq!(list.registrable_domain("localhost"), None);
+    }
+
+    #[pg_test]
+    fn test_embedded_list() {
+        let list = embedded_list();
+        assert!(list.len() > 5000);
+        assert_eq!(list.registrable_domain("mail.corp.example.co.uk"), Some("example.co.uk"));
+        assert_eq!(list.public_suffix("foo.bar.kawasaki.jp"), "bar.kawasaki.jp");
+        assert_eq!(list.public_suffix("city.kawasaki.jp"), "kawasaki.jp");
+    }
+
+    #[pg_test]
+    fn test_list_from_file() {
+        let path = std::env::temp_dir().join("pgdatatypes_plus_test_psl.dat");
+        std::fs::write(&path, "example\n").unwrap();
+
+        Spi::run(&format!("SET pgdatatypes_plus.public_suffix_list = '{}'", path.display())).unwrap();
+        let size = Spi::get_one::<i64>("SELECT public_suffix_list_size()").unwrap();
+        assert_eq!(size, Some(1));
+        assert_eq!(current_list().public_suffix("a.b.example"), "example");
+
+        Spi::run("RESET pgdatatypes_plus.public_suffix_list").unwrap();
+        assert!(current_list().len() > 5000);
+    }
+}
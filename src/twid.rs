@@ -1,9 +1,11 @@
 use pgrx::prelude::*;
-use pgrx::StringInfo;
-use std::cmp::Ordering;
+use pgrx::datum::{FromDatum, IntoDatum};
+use pgrx::wrappers::rust_regtypein;
+use std::ffi::{CStr, CString};
 use std::str::FromStr;
 use std::fmt::{self, Display};
-use serde::{Deserialize, Serialize};
+use crate::datum::{decode_legacy_cbor, impl_sql_type, report_input_error, take_message_bytes};
+use crate::error::{raise, InvalidValue};
 
 /// A Taiwan National ID type that stores Taiwan identification numbers in a validated format.
 /// 
@@ -19,60 +21,185 @@ use serde::{Deserialize, Serialize};
 /// 2. Apply coefficients [1, 8, 7, 6, 5, 4, 3, 2, 1, 1] to all 10 digits
 /// 3. Sum all products
 /// 4. Valid if sum is divisible by 10
-#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, PostgresType, PostgresEq, PostgresOrd)]
-#[inoutfuncs]
+///
+/// The ID is stored as a single `i64`, which makes `twid` a fixed-length,
+/// pass-by-value type. Comparison and hashing work on that integer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, PostgresEq, PostgresOrd, PostgresHash)]
 pub struct Twid {
-    data: String,
+    /// `region * 1_000_000_000 + digits`, where `region` is the letter's
+    /// position in the alphabet (A = 0) and `digits` the nine digits.
+    /// Using the alphabet position rather than the checksum region number
+    /// keeps integer order identical to the text order of the IDs.
+    packed: i64,
+}
+
+const DIGITS_RADIX: i64 = 1_000_000_000;
+
+impl Twid {
+    /// The uppercase region letter
+    fn region(&self) -> char {
+        (b'A' + (self.packed / DIGITS_RADIX) as u8) as char
+    }
+
+    /// The nine digits following the region letter
+    fn digits(&self) -> i64 {
+        self.packed % DIGITS_RADIX
+    }
+}
+
+impl Twid {
+    /// Encodes the ID in the binary wire format used by `twid_send`: the
+    /// packed value as an 8-byte big-endian signed integer, i.e. the region
+    /// letter's alphabet position (A = 0) times 1,000,000,000 plus the nine
+    /// digits. `A123456789` is sent as `123456789`, `F131232216` as
+    /// `5131232216`. The format is stable across releases.
+    fn to_wire(self) -> [u8; 8] {
+        self.packed.to_be_bytes()
+    }
+
+    /// Decodes and validates a value in the binary wire format.
+    fn from_wire(bytes: &[u8]) -> Result<Twid, InvalidValue> {
+        let bytes: [u8; 8] = bytes.try_into().map_err(|_| {
+            InvalidValue::new(INVALID_TWID).with_detail(format!("Expected 8 bytes, got {}.", bytes.len()))
+        })?;
+        let packed = i64::from_be_bytes(bytes);
+        if !(0..26 * DIGITS_RADIX).contains(&packed) {
+            return Err(InvalidValue::new(INVALID_TWID)
+                .with_detail(format!("The value {} is out of range.", packed)));
+        }
+
+        // Run the full validation, including the checksum
+        Twid::from_str(&Twid { packed }.to_string())
+    }
 }
 
 impl FromStr for Twid {
-    type Err = &'static str;
+    type Err = InvalidValue;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if !is_valid_taiwan_id(s) {
-            return Err("invalid Taiwan National ID format");
-        }
+        validate_taiwan_id(s)?;
+
+        // validate_taiwan_id guarantees one ASCII letter followed by nine digits
+        let region = s.as_bytes()[0].to_ascii_uppercase() - b'A';
+        let digits: i64 = s[1..].parse().map_err(|_| InvalidValue::new(INVALID_TWID))?;
 
         Ok(Twid {
-            data: s.to_uppercase(),
+            packed: region as i64 * DIGITS_RADIX + digits,
         })
     }
 }
 
-impl PartialOrd for Twid {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+impl Display for Twid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{:09}", self.region(), self.digits())
     }
 }
 
-impl Ord for Twid {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.data.cmp(&other.data)
+impl FromDatum for Twid {
+    unsafe fn from_polymorphic_datum(datum: pg_sys::Datum, is_null: bool, _typoid: pg_sys::Oid) -> Option<Self> {
+        if is_null {
+            None
+        } else {
+            Some(Twid {
+                packed: datum.value() as i64,
+            })
+        }
     }
 }
 
-impl Display for Twid {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.data)
+impl IntoDatum for Twid {
+    fn into_datum(self) -> Option<pg_sys::Datum> {
+        Some(pg_sys::Datum::from(self.packed))
     }
-}
 
-impl InOutFuncs for Twid {
-    fn input(input: &std::ffi::CStr) -> Twid {
-        let input_str = input.to_str().unwrap_or_else(|e| {
-            error!("invalid UTF-8 in TWID input: {}", e);
-        });
-        
-        Twid::from_str(input_str).unwrap_or_else(|e| {
-            error!("invalid input syntax for type twid: {}", e);
-        })
+    fn type_oid() -> pg_sys::Oid {
+        rust_regtypein::<Self>()
     }
+}
+
+impl_sql_type!(Twid, "twid");
+
+extension_sql!(
+    r#"
+CREATE TYPE twid;
+
+CREATE FUNCTION twid_in(cstring) RETURNS twid
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'twid_in_wrapper';
+
+CREATE FUNCTION twid_out(twid) RETURNS cstring
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'twid_out_wrapper';
+
+CREATE FUNCTION twid_recv(internal) RETURNS twid
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'twid_recv_wrapper';
 
-    fn output(&self, buffer: &mut StringInfo) {
-        buffer.push_str(&self.data);
+CREATE FUNCTION twid_send(twid) RETURNS bytea
+    IMMUTABLE STRICT PARALLEL SAFE
+    LANGUAGE c AS 'MODULE_PATHNAME', 'twid_send_wrapper';
+
+CREATE TYPE twid (
+    INTERNALLENGTH = 8,
+    PASSEDBYVALUE,
+    ALIGNMENT = double,
+    INPUT = twid_in,
+    OUTPUT = twid_out,
+    RECEIVE = twid_recv,
+    SEND = twid_send,
+    STORAGE = plain
+);
+"#,
+    name = "twid_type",
+    creates = [Type(Twid)],
+);
+
+#[pg_extern(immutable, parallel_safe, sql = false)]
+fn twid_in(input: &CStr, fcinfo: pg_sys::FunctionCallInfo) -> Option<Twid> {
+    let parsed = match input.to_str() {
+        Ok(input_str) => Twid::from_str(input_str),
+        Err(e) => Err(InvalidValue::new("invalid UTF-8 in TWID input").with_detail(e.to_string())),
+    };
+
+    match parsed {
+        Ok(twid) => Some(twid),
+        Err(e) => {
+            report_input_error(fcinfo, "twid", e);
+            None
+        }
     }
 }
 
+#[pg_extern(immutable, parallel_safe, sql = false)]
+fn twid_out(input: Twid) -> CString {
+    // always one letter and nine digits
+    CString::new(input.to_string()).unwrap()
+}
+
+#[pg_extern(immutable, parallel_safe, sql = false)]
+fn twid_recv(mut internal: Internal) -> Twid {
+    let buf = unsafe { internal.get_mut::<pg_sys::StringInfoData>() }.unwrap();
+    let bytes = unsafe { take_message_bytes(buf) };
+
+    Twid::from_wire(bytes).unwrap_or_else(|e| raise(e.binary_input_report("twid")))
+}
+
+#[pg_extern(immutable, parallel_safe, sql = false)]
+fn twid_send(input: Twid) -> Vec<u8> {
+    input.to_wire().to_vec()
+}
+
+/// Converts a value stored by 0.0.1, a serde/CBOR encoded `{"data": <id>}`
+/// varlena. Only the 0.0.1--0.0.2 update script declares it, as the cast
+/// from the renamed 0.0.1 type to `twid`.
+#[pg_extern(immutable, parallel_safe, sql = false)]
+fn twid_from_legacy(value: &[u8]) -> Twid {
+    let data = decode_legacy_cbor(value).unwrap_or_else(|| {
+        ereport!(ERROR, PgSqlErrorCode::ERRCODE_DATA_CORRUPTED, "corrupt twid value");
+    });
+    Twid::from_str(data).unwrap_or_else(|e| raise(e.input_report("twid")))
+}
+
 /// Cast TWID to text
 #[pg_cast(assignment)]
 fn cast_twid_to_text(input: Twid) -> String {
@@ -82,17 +209,13 @@ fn cast_twid_to_text(input: Twid) -> String {
 /// Cast text to TWID
 #[pg_cast(assignment)]
 fn cast_text_to_twid(input: &str) -> Twid {
-    Twid::from_str(input).unwrap_or_else(|e| {
-        error!("invalid input syntax for type twid: {}", e);
-    })
+    Twid::from_str(input).unwrap_or_else(|e| raise(e.input_report("twid")))
 }
 
 /// Create a Taiwan National ID from a text string
 #[pg_extern(immutable, parallel_safe)]
 fn twid(input: &str) -> Twid {
-    Twid::from_str(input).unwrap_or_else(|e| {
-        error!("invalid input syntax for type twid: {}", e);
-    })
+    Twid::from_str(input).unwrap_or_else(|e| raise(e.input_report("twid")))
 }
 
 /// Check if a string is a valid Taiwan National ID
@@ -105,33 +228,47 @@ fn is_valid_twid(input: &str) -> bool {
 /// Returns 'M' for male, 'F' for female, 'U' for unknown/other
 #[pg_extern(immutable, parallel_safe)]
 fn twid_gender(input: Twid) -> String {
-    get_gender_from_twid(&input.data)
+    get_gender_from_twid(&input.to_string())
 }
 
 /// Get the region code from a Taiwan National ID
 #[pg_extern(immutable, parallel_safe)]
 fn twid_region(input: Twid) -> String {
-    input.data.chars().next().unwrap_or('?').to_string()
+    input.region().to_string()
 }
 
 /// Validates a Taiwan National ID according to the official algorithm
 fn is_valid_taiwan_id(input: &str) -> bool {
+    validate_taiwan_id(input).is_ok()
+}
+
+const INVALID_TWID: &str = "invalid Taiwan National ID format";
+const TWID_HINT: &str = "A Taiwan National ID is a region letter followed by nine digits, e.g. A123456789.";
+
+/// Validates a Taiwan National ID according to the official algorithm,
+/// describing the first problem found
+fn validate_taiwan_id(input: &str) -> Result<(), InvalidValue> {
+    let invalid = |detail: String| {
+        Err(InvalidValue::new(INVALID_TWID).with_detail(detail))
+    };
+
     // Check basic format: 1 letter + 9 digits
-    if input.len() != 10 {
-        return false;
+    let chars: Vec<char> = input.to_uppercase().chars().collect();
+    if input.len() != 10 || chars.len() != 10 {
+        return Err(InvalidValue::new(INVALID_TWID)
+            .with_detail(format!("The ID has {} characters, expected 10.", input.chars().count()))
+            .with_hint(TWID_HINT));
     }
 
-    let chars: Vec<char> = input.to_uppercase().chars().collect();
-    
     // First character must be a letter
     if !chars[0].is_ascii_alphabetic() {
-        return false;
+        return invalid(format!("The first character \"{}\" is not a region letter A-Z.", chars[0]));
     }
 
     // Remaining 9 characters must be digits
-    for &c in &chars[1..] {
+    for (i, &c) in chars.iter().enumerate().skip(1) {
         if !c.is_ascii_digit() {
-            return false;
+            return invalid(format!("The character \"{}\" at position {} is not a digit.", c, i + 1));
         }
     }
     
@@ -140,13 +277,16 @@ fn is_valid_taiwan_id(input: &str) -> bool {
     // Note: 8=male foreign national, 9=female foreign national (new format)
     let gender_char = chars[1];
     if !matches!(gender_char, '1' | '2' | '8' | '9') {
-        return false;
+        return invalid(format!(
+            "The gender code \"{}\" at position 2 is not one of 1, 2, 8 or 9.",
+            gender_char
+        ));
     }
 
     // Convert region letter to number
     let region_code = match get_region_number(chars[0]) {
         Some(num) => num,
-        None => return false,
+        None => return invalid(format!("\"{}\" is not a known region letter.", chars[0])),
     };
 
     // Extract digits
@@ -169,7 +309,16 @@ fn is_valid_taiwan_id(input: &str) -> bool {
         .map(|(digit, coeff)| digit * coeff)
         .sum();
     
-    sum % 10 == 0
+    if sum % 10 != 0 {
+        // The check digit has weight 1, so it must bring the sum to a multiple of 10
+        let check_digit = digits[10];
+        let expected = (10 - (sum - check_digit) % 10) % 10;
+        return Err(InvalidValue::new(INVALID_TWID)
+            .with_detail(format!("The check digit is {}, expected {}.", check_digit, expected))
+            .with_hint("Check the ID for typing errors."));
+    }
+
+    Ok(())
 }
 
 /// Maps Taiwan region letters to their corresponding numbers
@@ -276,6 +425,33 @@ mod tests {
         assert!(twid.is_err());
     }
 
+    #[pg_test]
+    fn test_invalid_twid_details() {
+        let detail = |s: &str| Twid::from_str(s).unwrap_err().detail;
+
+        assert_eq!(detail("A123456788").as_deref(), Some("The check digit is 8, expected 9."));
+        assert_eq!(detail("A12345678").as_deref(), Some("The ID has 9 characters, expected 10."));
+        assert_eq!(detail("1123456789").as_deref(), Some("The first character \"1\" is not a region letter A-Z."));
+        assert_eq!(detail("A12345678A").as_deref(), Some("The character \"A\" at position 10 is not a digit."));
+        assert_eq!(
+            detail("A323456789").as_deref(),
+            Some("The gender code \"3\" at position 2 is not one of 1, 2, 8 or 9.")
+        );
+    }
+
+    #[pg_test]
+    fn test_invalid_input_sqlstate() {
+        Spi::run(
+            "CREATE FUNCTION pg_temp.sqlstate_of(input text) RETURNS text LANGUAGE plpgsql AS $$ \
+             BEGIN PERFORM input::twid; RETURN NULL; \
+             EXCEPTION WHEN OTHERS THEN RETURN SQLSTATE; END $$"
+        ).unwrap();
+
+        let sqlstate = Spi::get_one::<String>("SELECT pg_temp.sqlstate_of('A123456788')")
+            .expect("SPI result should not be NULL").unwrap();
+        assert_eq!(sqlstate, "22P02");
+    }
+
     #[pg_test]
     fn test_twid_gender() {
         assert_eq!(get_gender_from_twid("A123456789"), "M");
@@ -303,6 +479,95 @@ mod tests {
         
         assert!(twid_lower.is_ok());
         assert!(twid_upper.is_ok());
-        assert_eq!(twid_lower.unwrap().data, twid_upper.unwrap().data);
+        assert_eq!(twid_lower.unwrap(), twid_upper.unwrap());
+    }
+
+    #[pg_test]
+    fn test_packed_representation() {
+        let twid = Twid::from_str("f131232216").unwrap();
+        assert_eq!(twid.packed, 5 * DIGITS_RADIX + 131232216);
+        assert_eq!(twid.region(), 'F');
+        assert_eq!(twid.to_string(), "F131232216");
+
+        // Integer order matches the text order
+        let a = Twid::from_str("A123456789").unwrap();
+        let z = Twid::from_str("Z100000002").unwrap();
+        assert!(a < twid && twid < z);
+    }
+
+    #[pg_test]
+    fn test_wire_format() {
+        let twid = Twid::from_str("F131232216").unwrap();
+        assert_eq!(twid.to_wire(), 5131232216i64.to_be_bytes());
+        assert_eq!(Twid::from_wire(&twid.to_wire()), Ok(twid));
+
+        // Bad checksum, out of range and wrong length are all rejected
+        assert!(Twid::from_wire(&5131232217i64.to_be_bytes()).is_err());
+        assert!(Twid::from_wire(&(-1i64).to_be_bytes()).is_err());
+        assert!(Twid::from_wire(&26_000_000_000i64.to_be_bytes()).is_err());
+        assert!(Twid::from_wire(b"F131232216").is_err());
+
+        let sent = Spi::get_one::<Vec<u8>>("SELECT twid_send('A123456789')")
+            .expect("SPI result should not be NULL").unwrap();
+        assert_eq!(sent, 123456789i64.to_be_bytes());
+    }
+
+    #[cfg(any(feature = "pg16", feature = "pg17", feature = "pg18"))]
+    #[pg_test]
+    fn test_soft_input_errors() {
+        let valid = Spi::get_one::<bool>("SELECT pg_input_is_valid('A123456789', 'twid')")
+            .expect("SPI result should not be NULL").unwrap();
+        assert!(valid);
+
+        let valid = Spi::get_one::<bool>("SELECT pg_input_is_valid('A123456788', 'twid')")
+            .expect("SPI result should not be NULL").unwrap();
+        assert!(!valid);
+
+        let sqlstate = Spi::get_one::<String>(
+            "SELECT sql_error_code FROM pg_input_error_info('A123456788', 'twid')"
+        ).expect("SPI result should not be NULL").unwrap();
+        assert_eq!(sqlstate, "22P02");
+    }
+
+    #[pg_test]
+    fn test_legacy_conversion() {
+        // {"data": "A123456789"} as written by 0.0.1
+        let mut legacy = b"\xa1\x64data\x6a".to_vec();
+        legacy.extend_from_slice(b"A123456789");
+        assert_eq!(twid_from_legacy(&legacy), Twid::from_str("A123456789").unwrap());
+    }
+
+    #[pg_test]
+    #[should_panic(expected = "corrupt twid value")]
+    fn test_legacy_conversion_corrupt() {
+        twid_from_legacy(b"\xa1\x64data\x75short");
+    }
+
+    #[pg_test]
+    fn test_pass_by_value_storage() {
+        let size = Spi::get_one::<i32>("SELECT pg_column_size('A123456789'::twid)")
+            .expect("SPI result should not be NULL").unwrap();
+        assert_eq!(size, 8);
+
+        let byval = Spi::get_one::<bool>("SELECT typbyval FROM pg_type WHERE typname = 'twid'")
+            .expect("SPI result should not be NULL").unwrap();
+        assert!(byval);
+    }
+
+    #[pg_test]
+    fn test_hash_opclass() {
+        let same = Spi::get_one::<bool>(
+            "SELECT twid_hash('a123456789') = twid_hash('A123456789')"
+        ).expect("SPI result should not be NULL").unwrap();
+        assert!(same);
+
+        Spi::run("CREATE TABLE hash_twids (id twid)").unwrap();
+        Spi::run("CREATE INDEX hash_twids_idx ON hash_twids USING hash (id)").unwrap();
+        Spi::run("INSERT INTO hash_twids VALUES ('A123456789'), ('F131232216')").unwrap();
+
+        let count = Spi::get_one::<i64>(
+            "SELECT count(*) FROM hash_twids WHERE id = 'a123456789'"
+        ).expect("SPI result should not be NULL").unwrap();
+        assert_eq!(count, 1);
     }
 }
\ No newline at end of file